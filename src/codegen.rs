@@ -1,11 +1,17 @@
 use std::fmt::Write as FmtWrite;
 
-use crate::parse::{Node, NodeKind};
+use crate::parse::{GVar, Node, NodeKind, Program};
 use crate::token::TokenKind;
+use crate::types::Type;
 
-pub fn codegen(nodes: Vec<Node>) -> String {
+pub fn codegen(program: Program) -> String {
 	let mut s = String::new();
 	s.push_str(".intel_syntax noprefix\n");
+
+	// static変数
+	gen_data(&mut s, &program.gvars);
+
+	s.push_str(".text\n");
 	s.push_str(".globl main\n");
 
 	s.push_str("main:\n");
@@ -19,10 +25,8 @@ pub fn codegen(nodes: Vec<Node>) -> String {
 	s.push_str("  sub rsp, 208\n");
 	s.push_str("  # prologue end\n");
 
-	for node in nodes {
-		gen(&mut s, node);
-		// 式の評価結果がスタックから溢れないようにする
-		s.push_str("  pop rax\n");
+	for node in program.nodes {
+		gen_stmt(&mut s, node);
 		s.push_str("  # stmt-fin \n");
 	}
 
@@ -34,52 +38,107 @@ pub fn codegen(nodes: Vec<Node>) -> String {
 	s
 }
 
+fn gen_stmt(code: &mut String, node: Node) {
+	match node.kind {
+		NodeKind::Return(lhs) => {
+			gen(code, *lhs);
+			writeln!(code, "  pop rax").unwrap();
+			writeln!(code, "  mov rsp, rbp").unwrap();
+			writeln!(code, "  pop rbp").unwrap();
+			writeln!(code, "  ret").unwrap();
+		}
+		NodeKind::Decl(var, init) => {
+			// 初期化式がなければなにもしない
+			if let Some(init) = init {
+				gen_addr(code, *var);
+				gen(code, *init);
+				writeln!(code, "  pop rdi").unwrap();
+				writeln!(code, "  pop rax").unwrap();
+				gen_store(code, node.ty);
+			}
+		}
+		_ => {
+			gen(code, node);
+			// 式の評価結果がスタックから溢れないようにする
+			writeln!(code, "  pop rax").unwrap();
+		}
+	}
+}
+
 pub fn gen(code: &mut String, node: Node) -> &String {
 	match node.kind {
 		NodeKind::BinOp(token_kind, lhs, rhs) => {
+			// 両辺はparseで同じ型にそろえてある
+			let ty = lhs.ty;
+			let is_unsigned = ty.is_unsigned();
+
 			// Box外し
 			gen(code, *lhs);
 			gen(code, *rhs);
 
-			write!(code, "  pop rdi\n").unwrap();
-			write!(code, "  pop rax\n").unwrap();
+			writeln!(code, "  pop rdi").unwrap();
+			writeln!(code, "  pop rax").unwrap();
+
+			// 浮動小数点数はSSEで計算する
+			if ty.is_flonum() {
+				gen_float_binop(code, token_kind, ty);
+				writeln!(code, "  push rax").unwrap();
+				return code;
+			}
 
 			match token_kind {
 				TokenKind::Plus => {
-					write!(code, "  add rax, rdi\n").unwrap();
+					writeln!(code, "  add rax, rdi").unwrap();
 				}
 				TokenKind::Minus => {
-					write!(code, "  sub rax, rdi\n").unwrap();
+					writeln!(code, "  sub rax, rdi").unwrap();
 				}
 				TokenKind::Mul => {
-					write!(code, "  imul rax, rdi\n").unwrap();
+					writeln!(code, "  imul rax, rdi").unwrap();
 				}
 				TokenKind::Div => {
-					write!(code, "  cqo\n").unwrap();
-					write!(code, "  idiv rdi\n").unwrap()
+					if is_unsigned {
+						// unsignedの値はゼロ拡張してあるので、rdxを0にしてdiv
+						writeln!(code, "  mov rdx, 0").unwrap();
+						writeln!(code, "  div rdi").unwrap();
+					} else {
+						writeln!(code, "  cqo").unwrap();
+						writeln!(code, "  idiv rdi").unwrap();
+					}
 				}
 				TokenKind::EQ => {
-					write!(code, "  cmp rax, rdi\n").unwrap();
-					write!(code, "  sete al\n").unwrap();
-					write!(code, "  movzb rax, al\n").unwrap();
+					writeln!(code, "  cmp rax, rdi").unwrap();
+					writeln!(code, "  sete al").unwrap();
+					writeln!(code, "  movzb rax, al").unwrap();
 				}
 				TokenKind::LE => {
-					write!(code, "  cmp rax, rdi\n").unwrap();
-					write!(code, "  setle al\n").unwrap();
-					write!(code, "  movzb rax, al\n").unwrap();
+					writeln!(code, "  cmp rax, rdi").unwrap();
+					if is_unsigned {
+						writeln!(code, "  setbe al").unwrap();
+					} else {
+						writeln!(code, "  setle al").unwrap();
+					}
+					writeln!(code, "  movzb rax, al").unwrap();
 				}
 				TokenKind::LeftAngleBracket => {
-					write!(code, "  cmp rax, rdi\n").unwrap();
-					write!(code, "  setle al\n").unwrap();
-					write!(code, "  movzb rax, al\n").unwrap();
+					writeln!(code, "  cmp rax, rdi").unwrap();
+					if is_unsigned {
+						writeln!(code, "  setb al").unwrap();
+					} else {
+						writeln!(code, "  setl al").unwrap();
+					}
+					writeln!(code, "  movzb rax, al").unwrap();
 				}
 				TokenKind::NEQ => {
-					write!(code, "  cmp rax, rdi\n").unwrap();
-					write!(code, "  setne al\n").unwrap();
-					write!(code, "  movzb rax, al\n").unwrap();
+					writeln!(code, "  cmp rax, rdi").unwrap();
+					writeln!(code, "  setne al").unwrap();
+					writeln!(code, "  movzb rax, al").unwrap();
 				}
 				_ => panic!("unexpected token kind"),
 			}
+
+			// 計算結果を型の幅(32bit)に切り詰める
+			gen_extend(code, node.ty);
 		}
 		NodeKind::Num(v) => {
 			// https://doc.rust-lang.org/std/macro.write.html
@@ -87,7 +146,21 @@ pub fn gen(code: &mut String, node: Node) -> &String {
 			// sのヒープにコピーして、生成した文字列をdropすることになる
 			// writeを使えば、sのヒープに直接formatした文字列を書き込めるってことかな
 			// https://users.rust-lang.org/t/how-do-i-push-str-the-contents-of-a-variable/45594/6
-			write!(code, "  push {}\n", v).unwrap();
+			// pushの即値は32bitを符号拡張したものなので、unsignedの大きい値はraxを経由する
+			writeln!(code, "  mov rax, {}", v).unwrap();
+			writeln!(code, "  push rax").unwrap();
+			return code;
+		}
+		NodeKind::FNum(v) => {
+			// 浮動小数点数はビット列のままraxにいれてスタックにつむ
+			// floatは下位32bitを使う
+			let bits = if node.ty == Type::Float {
+				(v as f32).to_bits() as u64
+			} else {
+				v.to_bits()
+			};
+			writeln!(code, "  mov rax, {:#x} # {}", bits, v).unwrap();
+			writeln!(code, "  push rax").unwrap();
 			return code;
 		}
 		NodeKind::Assign(lhs, rhs) => {
@@ -95,46 +168,190 @@ pub fn gen(code: &mut String, node: Node) -> &String {
 			// =の場合、左辺値は必ず変数
 			// BinOpの中のTokenKindの中にAssignを生やしたほうがわかりやすいかもしれない
 			// その場合、nodeの所有権がgenにmoveしてしまわないようにする必要がある
-			if let NodeKind::LVar(_, offset) = lhs.kind {
-				let s = gen_lval(offset);
-				code.push_str(&s);
-			} else {
-				panic!("unexpected node: {:?}", lhs)
-			}
-
+			gen_addr(code, *lhs);
 			gen(code, *rhs);
 
-			write!(code, "  pop rdi\n").unwrap();
-			write!(code, "  pop rax\n").unwrap();
-			write!(code, "  mov [rax], rdi\n").unwrap();
-			write!(code, "  push rdi\n").unwrap();
+			writeln!(code, "  pop rdi").unwrap();
+			writeln!(code, "  pop rax").unwrap();
+			gen_store(code, node.ty);
+			writeln!(code, "  push rdi").unwrap();
 			return code;
 		}
 		// LVarは、AssignNodeのchildとして存在している場合はここにこないので注意。
-		NodeKind::LVar(_, offset) => {
-			let s = gen_lval(offset);
-			code.push_str(&s);
-			write!(code, "  pop rax\n").unwrap();
-			write!(code, "  mov rax, [rax]\n").unwrap();
-			write!(code, "  push rax\n").unwrap();
+		NodeKind::LVar(..) | NodeKind::GVar(..) => {
+			let ty = node.ty;
+			gen_addr(code, node);
+			writeln!(code, "  pop rax").unwrap();
+			gen_load(code, ty);
+			writeln!(code, "  push rax").unwrap();
 			return code;
 		}
-		NodeKind::Return(lhs) => {
+		NodeKind::Cast(lhs) => {
+			let from = lhs.ty;
 			gen(code, *lhs);
-			write!(code, "  pop rax\n").unwrap();
-			write!(code, "  mov rsp, rbp\n").unwrap();
-			write!(code, "  pop rbp\n").unwrap();
-			write!(code, "  ret\n").unwrap();
-			return code;
+			writeln!(code, "  pop rax").unwrap();
+			gen_cast(code, from, node.ty);
 		}
+		NodeKind::Return(_) | NodeKind::Decl(..) => panic!("unexpected node: {:?}", node),
 	}
 
-	// このコードは今の所BinOpときだけ実行してる
-	write!(code, "  push rax\n").unwrap();
+	// このコードは今の所BinOpとCastのときだけ実行してる
+	writeln!(code, "  push rax").unwrap();
 
 	code
 }
 
+// 浮動小数点数の二項演算
+// rax(左辺)とrdi(右辺)にビット列で入っている値をxmm0/xmm1にうつして計算し、結果をraxにいれる
+fn gen_float_binop(code: &mut String, token_kind: TokenKind, ty: Type) {
+	// addsd/addssのようにdoubleとfloatで命令のsuffixがかわる
+	let sx = if ty == Type::Double { "sd" } else { "ss" };
+	writeln!(code, "  movq xmm0, rax").unwrap();
+	writeln!(code, "  movq xmm1, rdi").unwrap();
+
+	match token_kind {
+		TokenKind::Plus => writeln!(code, "  add{} xmm0, xmm1", sx).unwrap(),
+		TokenKind::Minus => writeln!(code, "  sub{} xmm0, xmm1", sx).unwrap(),
+		TokenKind::Mul => writeln!(code, "  mul{} xmm0, xmm1", sx).unwrap(),
+		TokenKind::Div => writeln!(code, "  div{} xmm0, xmm1", sx).unwrap(),
+		// 比較はNaNのときにparity flagがたつので、それも見る
+		TokenKind::EQ => {
+			writeln!(code, "  ucomi{} xmm0, xmm1", sx).unwrap();
+			writeln!(code, "  sete al").unwrap();
+			writeln!(code, "  setnp dl").unwrap();
+			writeln!(code, "  and al, dl").unwrap();
+			writeln!(code, "  movzb rax, al").unwrap();
+			return;
+		}
+		TokenKind::NEQ => {
+			writeln!(code, "  ucomi{} xmm0, xmm1", sx).unwrap();
+			writeln!(code, "  setne al").unwrap();
+			writeln!(code, "  setp dl").unwrap();
+			writeln!(code, "  or al, dl").unwrap();
+			writeln!(code, "  movzb rax, al").unwrap();
+			return;
+		}
+		// a <= b は b >= a として比較すると、NaNのときに0になってくれる
+		TokenKind::LE => {
+			writeln!(code, "  ucomi{} xmm1, xmm0", sx).unwrap();
+			writeln!(code, "  setae al").unwrap();
+			writeln!(code, "  movzb rax, al").unwrap();
+			return;
+		}
+		TokenKind::LeftAngleBracket => {
+			writeln!(code, "  ucomi{} xmm1, xmm0", sx).unwrap();
+			writeln!(code, "  seta al").unwrap();
+			writeln!(code, "  movzb rax, al").unwrap();
+			return;
+		}
+		_ => panic!("unexpected token kind"),
+	}
+
+	writeln!(code, "  movq rax, xmm0").unwrap();
+}
+
+// raxの値をfromの型からtoの型に変換する
+fn gen_cast(code: &mut String, from: Type, to: Type) {
+	if from == to {
+		return;
+	}
+
+	match (from.is_flonum(), to.is_flonum()) {
+		// 整数同士
+		(false, false) => gen_extend(code, to),
+		// 整数 → 浮動小数点数
+		// raxはintなら符号拡張、unsignedならゼロ拡張してあるので64bitのまま変換すればいい
+		(false, true) => {
+			let sx = if to == Type::Double { "sd" } else { "ss" };
+			writeln!(code, "  cvtsi2{} xmm0, rax", sx).unwrap();
+			writeln!(code, "  movq rax, xmm0").unwrap();
+		}
+		// 浮動小数点数 → 整数
+		(true, false) => {
+			let sx = if from == Type::Double { "sd" } else { "ss" };
+			writeln!(code, "  movq xmm0, rax").unwrap();
+			writeln!(code, "  cvtt{}2si rax, xmm0", sx).unwrap();
+			gen_extend(code, to);
+		}
+		// float ⇔ double
+		(true, true) => {
+			writeln!(code, "  movq xmm0, rax").unwrap();
+			if to == Type::Double {
+				writeln!(code, "  cvtss2sd xmm0, xmm0").unwrap();
+			} else {
+				writeln!(code, "  cvtsd2ss xmm0, xmm0").unwrap();
+			}
+			writeln!(code, "  movq rax, xmm0").unwrap();
+		}
+	}
+}
+
+// raxの下位32bitを型にあわせて64bitに拡張する
+// レジスタ上の値は、intなら符号拡張、unsignedならゼロ拡張した状態でもつことにする
+fn gen_extend(code: &mut String, ty: Type) {
+	match ty {
+		Type::Int => writeln!(code, "  movsxd rax, eax").unwrap(),
+		// 32bitレジスタへのmovは上位32bitを0にする
+		Type::UInt => writeln!(code, "  mov eax, eax").unwrap(),
+		// 浮動小数点数はビット列なのでなにもしない
+		Type::Float | Type::Double => {}
+	}
+}
+
+// raxのアドレスから値を読んでraxに入れる
+fn gen_load(code: &mut String, ty: Type) {
+	match ty {
+		Type::Int => writeln!(code, "  movsxd rax, dword ptr [rax]").unwrap(),
+		Type::UInt | Type::Float => writeln!(code, "  mov eax, dword ptr [rax]").unwrap(),
+		Type::Double => writeln!(code, "  mov rax, [rax]").unwrap(),
+	}
+}
+
+// raxのアドレスにrdiの値を書き込む
+fn gen_store(code: &mut String, ty: Type) {
+	match ty {
+		// int/unsigned/floatは4byte
+		Type::Int | Type::UInt | Type::Float => writeln!(code, "  mov dword ptr [rax], edi").unwrap(),
+		Type::Double => writeln!(code, "  mov [rax], rdi").unwrap(),
+	}
+}
+
+// 変数のアドレスをスタックにつむ
+// =の左辺が変数かどうかは意味解析でみてあるので、ここには変数しかこない
+fn gen_addr(code: &mut String, node: Node) {
+	match node.kind {
+		NodeKind::LVar(_, offset) => code.push_str(&gen_lval(offset)),
+		NodeKind::GVar(_, label) => {
+			writeln!(code, "  lea rax, [rip + {}]", label).unwrap();
+			writeln!(code, "  push rax").unwrap();
+		}
+		_ => panic!("unexpected node: {:?}", node),
+	}
+}
+
+// static変数を.dataに出力する
+// .globlをつけないのでファイルの外からは見えない
+fn gen_data(code: &mut String, gvars: &[GVar]) {
+	if gvars.is_empty() {
+		return;
+	}
+
+	code.push_str(".data\n");
+	for gvar in gvars {
+		let size = match gvar.ty {
+			Type::Int | Type::UInt | Type::Float => 4,
+			Type::Double => 8,
+		};
+		writeln!(code, ".align {}", size).unwrap();
+		writeln!(code, "{}:", gvar.label).unwrap();
+		match (gvar.init, size) {
+			(Some(bits), 4) => writeln!(code, "  .long {:#x}", bits).unwrap(),
+			(Some(bits), _) => writeln!(code, "  .quad {:#x}", bits).unwrap(),
+			(None, _) => writeln!(code, "  .zero {}", size).unwrap(),
+		}
+	}
+}
+
 fn gen_lval(offset: usize) -> String {
 	// ベースポインタから指定されたoffsetの値を引いたアドレスをスタックにつんで返す
 	let s = format!(
@@ -1,9 +1,142 @@
 use std::env;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+
+use crate::diagnostic::DiagnosticsFormat;
+use crate::dump::DumpFormat;
+use crate::warning::{self, Warning};
+use crate::{is_c_source, Result};
+
+pub const USAGE: &str = "使い方: rust9cc [オプション] ファイル...
+ファイルに - を指定すると標準入力からCのソースを読む
+Cのファイルはファイル全体がmainの本体になるので、リンクするときは1つだけ
+
+オプション:
+  -o <file>         出力先のファイル - なら標準出力
+  -S                アセンブリを出力する
+  -c                オブジェクトファイルを出力する
+  -E                プリプロセスした結果を出力する
+  --emit=<kind>     出力するもの tokens|ast|asm
+  --dump-tokens[=json]
+                    プリプロセス前のトークン列を位置と綴りつきで出力する
+  --dump-ast[=json] 構文木を型と位置つきで出力する
+  -I <dir>          インクルードパスを追加する
+  -D <name>[=<val>] マクロを定義する
+  -U <name>         マクロを未定義にする
+  -L <dir>          ライブラリを探すディレクトリを追加する
+  -l <lib>          ライブラリをリンクする
+  -save-temps       途中でつくった .s/.o を消さずに残す
+  -W<warning>       警告を有効にする unused-variable|uninitialized|return-type|
+                    parentheses
+  -Wno-<warning>    警告を無効にする あとの -Wall/-Wextra でも有効にならない
+  -Wall             全部の警告を有効にする
+  -Wextra           -Wuninitialized を有効にする
+  -Werror           警告をエラーにする
+  --diagnostics-format=<format>
+                    エラーと警告の出力形式 text|json|sarif
+  -fcolor-diagnostics
+  -fno-color-diagnostics
+                    エラーと警告に色をつける/つけない
+                    指定がなければ標準エラー出力が端末で NO_COLOR がないときにつける
+  -ferror-limit=<n> エラーがn個出たらコンパイルをやめる 0なら制限なし(デフォルトは20)
+  --help            この使い方を表示する
+  --version         バージョンを表示する
+";
 
 #[derive(Debug)]
 pub struct Config {
-	pub entry: String,
-	pub dist: String,
+	// 入力のファイル
+	// .c はコンパイル、.s はアセンブルして、それ以外(.oなど)はそのままリンクする
+	pub inputs: Vec<String>,
+	// -o で指定された出力先 Noneならemitごとのデフォルト
+	pub output: Option<String>,
+	// なにを出力するか
+	pub emit: Emit,
+	// -I で指定されたインクルードパス
+	pub include_paths: Vec<String>,
+	// -D/-U で指定されたマクロ 指定された順に処理する
+	pub macros: Vec<MacroDef>,
+	// -L/-l リンクするときにccにわたす
+	pub lib_paths: Vec<String>,
+	pub libs: Vec<String>,
+	// -save-temps 一時ファイルをカレントディレクトリに残す
+	pub save_temps: bool,
+	// 有効になっている警告 -W/-Wno- を指定された順に処理する
+	// 指定がなければなにも出さない mainの最後にreturnがなくてもclangは警告しないので
+	pub warnings: Vec<Warning>,
+	// -Wno- で無効にした警告 -Wall/-Wextra では有効にしない
+	// gccとおなじく、名前を指定した -W ならまた有効にできる
+	pub disabled_warnings: Vec<Warning>,
+	// -Werror 警告をエラーにする
+	pub werror: bool,
+	// --diagnostics-format エラーと警告をどの形式で出すか
+	pub diagnostics_format: DiagnosticsFormat,
+	// -fcolor-diagnostics/-fno-color-diagnostics Noneなら端末かどうかで決める
+	pub color: Option<bool>,
+	// -ferror-limit=N この数だけエラーが出たら打ち切る 0なら打ち切らない
+	pub error_limit: usize,
+	// --help/--version が指定されたらコンパイルせずに表示して終わる
+	pub help: bool,
+	pub version: bool,
+}
+
+// どこまで処理して、なにを出力するか
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+	// -E プリプロセスした結果のCのソース
+	Source,
+	// --emit=tokens / --dump-tokens[=json]
+	Tokens(DumpFormat),
+	// --emit=ast / --dump-ast[=json]
+	Ast(DumpFormat),
+	// -S / --emit=asm
+	Asm,
+	// -c
+	Obj,
+	// 指定がなければリンクして実行ファイルまでつくる
+	Exe,
+}
+
+#[derive(Debug, Clone)]
+pub enum MacroDef {
+	// -D NAME=value -D NAME のときは値は1
+	Define(String, String),
+	// -U NAME
+	Undef(String),
+}
+
+impl MacroDef {
+	pub fn new_define(arg: &str) -> Self {
+		match arg.find('=') {
+			Some(i) => MacroDef::Define(arg[..i].to_string(), arg[i + 1..].to_string()),
+			None => MacroDef::Define(arg.to_string(), "1".to_string()),
+		}
+	}
+}
+
+// compile_strにわたすときは必要なところだけ指定してあとはdefaultにする
+// Config { include_paths: vec!["include".to_string()], ..Config::default() }
+impl Default for Config {
+	fn default() -> Self {
+		Config {
+			inputs: Vec::new(),
+			output: None,
+			emit: Emit::Exe,
+			include_paths: Vec::new(),
+			macros: Vec::new(),
+			lib_paths: Vec::new(),
+			libs: Vec::new(),
+			save_temps: false,
+			warnings: Vec::new(),
+			disabled_warnings: Vec::new(),
+			werror: false,
+			diagnostics_format: DiagnosticsFormat::Text,
+			color: None,
+			error_limit: 20,
+			help: false,
+			version: false,
+		}
+	}
 }
 
 // 今回のエラー処理だと、出力されるエラー情報がうすいので、どっかでこちらのパッケージを利用した方法を参考にさせていただこう。
@@ -12,30 +145,145 @@ pub struct Config {
 // https://doc.rust-jp.rs/rust-by-example-ja/error/multiple_error_types/wrap_error.html
 // 組み込みErrorを独自エラー型に変換するのがめんどくさそうね
 
-impl<'a> Config {
-	// MEMO デフォルトパラメータみたいなことができれば
-	// MEMO const値なんだけど、ライフライムを指定する意味はあるのかな
-	const DEFAULT_ENTRY: &'a str = "tmp.c";
-	const DEFAULT_DIST: &'a str = "tmp.s";
-
-	pub fn new(mut args: env::Args) -> Config {
+impl Config {
+	pub fn new(mut args: env::Args) -> Result<Config> {
 		args.next();
-		// MEMO lenってnextイテレータすすめると減るのかな
-		//if args.len() != 2 {
-		//	return Err("引数の数が間違ってるよ".into());
-		//}
-
-		let entry = match args.next() {
-			Some(args) => args,
-			// MEMO Copyになるけどいいかな
-			None => Self::DEFAULT_ENTRY.to_string(),
-		};
-
-		let dist = match args.next() {
-			Some(args) => args,
-			None => Self::DEFAULT_DIST.to_string(),
-		};
-
-		Config { entry, dist }
+
+		// -I dir と -Idir のどちらの書き方もできる(-D/-U/-o/-L/-lも同じ)
+		let mut config = Config::default();
+		while let Some(arg) = args.next() {
+			match arg.as_str() {
+				"--help" => config.help = true,
+				"--version" => config.version = true,
+				"-E" => config.emit = Emit::Source,
+				"-S" => config.emit = Emit::Asm,
+				"-c" => config.emit = Emit::Obj,
+				"-save-temps" => config.save_temps = true,
+				"-Wall" => config.enable_warnings(&warning::ALL),
+				"-Wextra" => config.enable_warnings(&warning::EXTRA),
+				"-Werror" => config.werror = true,
+				"-fcolor-diagnostics" => config.color = Some(true),
+				"-fno-color-diagnostics" => config.color = Some(false),
+				"--dump-tokens" => config.emit = Emit::Tokens(DumpFormat::Text),
+				"--dump-tokens=json" => config.emit = Emit::Tokens(DumpFormat::Json),
+				"--dump-ast" => config.emit = Emit::Ast(DumpFormat::Text),
+				"--dump-ast=json" => config.emit = Emit::Ast(DumpFormat::Json),
+				"-o" => config.output = Some(value(&arg, args.next())?),
+				"-I" => config.include_paths.push(value(&arg, args.next())?),
+				"-D" => config.macros.push(MacroDef::new_define(&value(&arg, args.next())?)),
+				"-U" => config.macros.push(MacroDef::Undef(value(&arg, args.next())?)),
+				"-L" => config.lib_paths.push(value(&arg, args.next())?),
+				"-l" => config.libs.push(value(&arg, args.next())?),
+				_ => {
+					if let Some(kind) = arg.strip_prefix("--emit=") {
+						config.emit = match kind {
+							"tokens" => Emit::Tokens(DumpFormat::Text),
+							"ast" => Emit::Ast(DumpFormat::Text),
+							"asm" => Emit::Asm,
+							"ir" => return Err("--emit=ir: 中間表現はなくて構文木から直接アセンブリを出力しているので対応していません".into()),
+							_ => return Err(format!("--emit={}: tokens, ast, asm のどれかを指定してください", kind).into()),
+						};
+					} else if let Some(format) = arg.strip_prefix("--diagnostics-format=") {
+						config.diagnostics_format = match format {
+							"text" => DiagnosticsFormat::Text,
+							"json" => DiagnosticsFormat::Json,
+							"sarif" => DiagnosticsFormat::Sarif,
+							_ => return Err(format!("{}: text, json, sarif のどれかを指定してください", arg).into()),
+						};
+					} else if let Some(name) = arg.strip_prefix("-Wno-") {
+						let warning = warning_from_name(name)?;
+						config.warnings.retain(|w| *w != warning);
+						if !config.disabled_warnings.contains(&warning) {
+							config.disabled_warnings.push(warning);
+						}
+					} else if let Some(name) = arg.strip_prefix("-W") {
+						let warning = warning_from_name(name)?;
+						config.disabled_warnings.retain(|w| *w != warning);
+						config.enable_warnings(&[warning]);
+					} else if let Some(n) = arg.strip_prefix("-ferror-limit=") {
+						config.error_limit = n
+							.parse()
+							.map_err(|_| format!("{}: エラーの数を0以上の整数で指定してください", arg))?;
+					} else if let Some(path) = arg.strip_prefix("-o") {
+						config.output = Some(path.to_string());
+					} else if let Some(path) = arg.strip_prefix("-I") {
+						config.include_paths.push(path.to_string());
+					} else if let Some(def) = arg.strip_prefix("-D") {
+						config.macros.push(MacroDef::new_define(def));
+					} else if let Some(name) = arg.strip_prefix("-U") {
+						config.macros.push(MacroDef::Undef(name.to_string()));
+					} else if let Some(dir) = arg.strip_prefix("-L") {
+						config.lib_paths.push(dir.to_string());
+					} else if let Some(lib) = arg.strip_prefix("-l") {
+						config.libs.push(lib.to_string());
+					} else if arg.starts_with('-') && arg != "-" {
+						return Err(format!("{}: 知らないオプションです (--help で使い方を表示します)", arg).into());
+					} else {
+						config.inputs.push(arg);
+					}
+				}
+			}
+		}
+
+		if config.help || config.version {
+			return Ok(config);
+		}
+		if config.inputs.is_empty() {
+			return Err("入力ファイルがありません".into());
+		}
+		// 入力ごとに出力ができるので、-o がどれのことかわからない
+		if config.output.is_some() && config.emit != Emit::Exe && config.inputs.len() > 1 {
+			return Err("-o は入力ファイルが複数のときは -E/-S/-c/--emit といっしょに指定できません".into());
+		}
+		// 関数定義がまだないので、Cのファイルはどれもmainを定義する
+		// 2つ以上リンクするとmainが重複するので、リンクする前にエラーにする
+		if config.emit == Emit::Exe && config.inputs.iter().filter(|input| is_c_source(input)).count() > 1 {
+			return Err("Cのファイルは1つしかリンクできません (どれもファイル全体がmainの本体になるので、mainが重複します)".into());
+		}
+		Ok(config)
+	}
+
+	fn enable_warnings(&mut self, warnings: &[Warning]) {
+		for warning in warnings {
+			if !self.warnings.contains(warning) && !self.disabled_warnings.contains(warning) {
+				self.warnings.push(*warning);
+			}
+		}
+	}
+
+	// エラーと警告に色をつけるか
+	// 指定がなければ、NO_COLORが空でなければつけない、そうでなければ標準エラー出力が端末ならつける
+	// https://no-color.org/
+	pub fn color_diagnostics(&self) -> bool {
+		match self.color {
+			Some(color) => color,
+			None => env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && io::stderr().is_terminal(),
+		}
 	}
+
+	// -o がないときの出力先 Noneなら標準出力
+	// gccとおなじく -S/-c は入力のファイル名の拡張子をかえて、カレントディレクトリに出す
+	// 標準入力から読んだときの -S はパイプでつなげられるように標準出力に出す
+	pub fn output_path(&self, input: &str) -> Option<String> {
+		if let Some(ref path) = self.output {
+			return Some(path.clone());
+		}
+		let stem = Path::new(input).file_stem().unwrap_or_default().to_string_lossy();
+		match self.emit {
+			Emit::Source | Emit::Tokens(_) | Emit::Ast(_) => None,
+			Emit::Asm if input == "-" => None,
+			Emit::Asm => Some(format!("{}.s", stem)),
+			Emit::Obj => Some(format!("{}.o", stem)),
+			Emit::Exe => Some("a.out".to_string()),
+		}
+	}
+}
+
+// -o file のように値をとるオプションの値
+fn value(option: &str, value: Option<String>) -> Result<String> {
+	value.ok_or_else(|| format!("{} の後に値がありません", option).into())
+}
+
+fn warning_from_name(name: &str) -> Result<Warning> {
+	Warning::from_name(name).ok_or_else(|| format!("-W{}: 知らない警告です (--help で使える警告を表示します)", name).into())
 }
@@ -0,0 +1,560 @@
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::dump::loc_json;
+use crate::json::Json;
+use crate::preprocess::builtin_header;
+use crate::token::Loc;
+use crate::warning::Warning;
+
+// --diagnostics-format=json のスキーマのバージョン
+const SCHEMA_VERSION: i64 = 1;
+
+// エラーや警告の重さ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+	Error,
+	Warning,
+	Note,
+}
+
+impl fmt::Display for Severity {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Severity::Error => write!(f, "error"),
+			Severity::Warning => write!(f, "warning"),
+			Severity::Note => write!(f, "note"),
+		}
+	}
+}
+
+/// エラーの種類 JSONのcodeとSARIFのruleIdに出す
+/// 警告は -Wunused-variable のようなオプションをcodeにするので、これはエラーだけ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCode {
+	/// オプションやファイルの読み書き、as/ccの失敗など、ソースの外のエラー
+	Driver,
+	/// トークナイズできない文字や数値
+	InvalidToken,
+	/// #include や #define などのディレクティブのエラー
+	Preprocessor,
+	/// そのほかの構文エラー
+	Syntax,
+	/// ; や ) などの決まったトークンがない
+	ExpectedToken,
+	/// 式がくるはずのところに式がない
+	ExpectedExpression,
+	/// 型や記憶域クラスの指定、変数名がおかしい
+	InvalidDeclaration,
+	/// static変数の初期化式が定数ではない
+	NonConstantInitializer,
+	/// ブロック内のextern宣言を初期化している
+	ExternInitializer,
+	/// 宣言されていない変数
+	UndeclaredIdentifier,
+	/// 同じスコープで同じ名前を2回宣言した
+	Redeclaration,
+	/// extern宣言の型が前の宣言とちがう
+	ConflictingTypes,
+	/// 代入の左辺が変数ではない
+	NotAssignable,
+	/// -ferror-limit で打ち切った
+	TooManyErrors,
+}
+
+impl ErrorCode {
+	/// codeに出す名前
+	pub fn name(self) -> &'static str {
+		match self {
+			ErrorCode::Driver => "driver",
+			ErrorCode::InvalidToken => "invalid-token",
+			ErrorCode::Preprocessor => "preprocessor",
+			ErrorCode::Syntax => "syntax",
+			ErrorCode::ExpectedToken => "expected-token",
+			ErrorCode::ExpectedExpression => "expected-expression",
+			ErrorCode::InvalidDeclaration => "invalid-declaration",
+			ErrorCode::NonConstantInitializer => "non-constant-initializer",
+			ErrorCode::ExternInitializer => "extern-initializer",
+			ErrorCode::UndeclaredIdentifier => "undeclared-identifier",
+			ErrorCode::Redeclaration => "redeclaration",
+			ErrorCode::ConflictingTypes => "conflicting-types",
+			ErrorCode::NotAssignable => "not-assignable",
+			ErrorCode::TooManyErrors => "too-many-errors",
+		}
+	}
+}
+
+// コンパイラが出すエラーや警告
+// 文字列にしてしまわずに、位置とメッセージを分けて持っておく
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+	pub severity: Severity,
+	// ファイルの読み込みに失敗したときのように、位置がないこともある
+	pub loc: Option<Loc>,
+	pub message: String,
+	// locからのバイト数 わからなければ0
+	pub len: usize,
+	// 警告ならどの警告か JSONなどでは -Wunused-variable のようなオプションをcodeとして出す
+	pub warning: Option<Warning>,
+	// エラーならどのエラーか 警告でもas/ccの出力のようにオプションがないものはこちら
+	pub code: Option<ErrorCode>,
+	// マクロを展開したところのような補足
+	pub notes: Vec<Diagnostic>,
+	// こう直せばいいという提案
+	pub fixits: Vec<FixIt>,
+}
+
+// locからlenバイトをtextに置きかえる lenが0なら挿入
+#[derive(Debug, Clone)]
+pub struct FixIt {
+	pub loc: Loc,
+	pub len: usize,
+	pub text: String,
+}
+
+impl Diagnostic {
+	pub fn error(loc: &Loc, message: impl Into<String>) -> Self {
+		Diagnostic {
+			severity: Severity::Error,
+			loc: Some(loc.clone()),
+			message: message.into(),
+			len: 0,
+			warning: None,
+			code: None,
+			notes: Vec::new(),
+			fixits: Vec::new(),
+		}
+	}
+
+	pub fn note(loc: &Loc, message: impl Into<String>) -> Self {
+		Diagnostic {
+			severity: Severity::Note,
+			..Diagnostic::error(loc, message)
+		}
+	}
+
+	pub fn warning(loc: &Loc, warning: Warning, message: impl Into<String>) -> Self {
+		Diagnostic {
+			severity: Severity::Warning,
+			warning: Some(warning),
+			..Diagnostic::error(loc, message)
+		}
+	}
+
+	// 変数名のように長さのわかるところを指す
+	pub fn with_len(mut self, len: usize) -> Self {
+		self.len = len;
+		self
+	}
+
+	pub fn with_code(mut self, code: ErrorCode) -> Self {
+		self.code = Some(code);
+		self
+	}
+
+	// まだ種類の決まっていないエラーをcodeにする
+	// 構文解析のように、どこで出たかで種類が決まるエラーにつかう
+	pub fn or_code(mut self, code: ErrorCode) -> Self {
+		if self.warning.is_none() && self.code.is_none() {
+			self.code = Some(code);
+		}
+		self
+	}
+
+	// JSONのcodeとSARIFのruleId
+	fn code_name(&self) -> Option<String> {
+		match (self.warning, self.code) {
+			(Some(warning), _) => Some(warning.flag()),
+			(None, Some(code)) => Some(code.name().to_string()),
+			(None, None) => None,
+		}
+	}
+
+	// 警告なら後ろに [-Wunused-variable] のようにオプションをつける
+	// -Werror でエラーにしたときは [-Werror,-Wunused-variable]
+	fn message_with_flag(&self) -> String {
+		match (self.severity, self.warning) {
+			(Severity::Error, Some(warning)) => format!("{} [-Werror,{}]", self.message, warning.flag()),
+			(_, Some(warning)) => format!("{} [{}]", self.message, warning.flag()),
+			_ => self.message.clone(),
+		}
+	}
+
+	// 位置のないエラー
+	// Box<dyn Error>で返ってきたエラーもこれにつつむ
+	pub fn without_loc(message: impl Into<String>) -> Self {
+		Diagnostic {
+			severity: Severity::Error,
+			loc: None,
+			message: message.into(),
+			len: 0,
+			warning: None,
+			code: None,
+			notes: Vec::new(),
+			fixits: Vec::new(),
+		}
+	}
+}
+
+// loc: error: message
+// ソースの行は出さないので、ライブラリで文字列にするときなどにつかう
+// 補足は次の行から同じ形で出す
+impl fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if let Some(ref loc) = self.loc {
+			write!(f, "{}: ", loc)?;
+		}
+		write!(f, "{}: {}", self.severity, self.message_with_flag())?;
+		for note in &self.notes {
+			write!(f, "\n{}", note)?;
+		}
+		Ok(())
+	}
+}
+
+impl error::Error for Diagnostic {}
+
+// -ferror-limit のエラーの数に達したときに最後に足すエラー
+pub(crate) fn too_many_errors(error_limit: usize) -> Diagnostic {
+	Diagnostic::without_loc(format!("エラーが多すぎるので中断します (-ferror-limit={})", error_limit)).with_code(ErrorCode::TooManyErrors)
+}
+
+// 1回のコンパイルで出たエラーと警告
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+	pub fn has_errors(&self) -> bool {
+		self.0.iter().any(|d| d.severity == Severity::Error)
+	}
+
+	// formatにしたがって文字列にする
+	// Textならclangのようにソースの行と下線もつける colorなら色もつける
+	// JSON/SARIFは診断がなくても空の配列を出す
+	pub fn render(&self, format: DiagnosticsFormat, color: bool) -> String {
+		match format {
+			DiagnosticsFormat::Text => {
+				let mut out = String::new();
+				for diagnostic in &self.0 {
+					render_text(&mut out, diagnostic, color);
+				}
+				out
+			}
+			DiagnosticsFormat::Json => Json::Object(vec![
+				("version", Json::Int(SCHEMA_VERSION)),
+				("diagnostics", Json::Array(self.0.iter().map(diagnostic_json).collect())),
+			])
+			.pretty(),
+			DiagnosticsFormat::Sarif => sarif(&self.0).pretty(),
+		}
+	}
+}
+
+impl fmt::Display for Diagnostics {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for (i, diagnostic) in self.0.iter().enumerate() {
+			if i > 0 {
+				writeln!(f)?;
+			}
+			write!(f, "{}", diagnostic)?;
+		}
+		Ok(())
+	}
+}
+
+impl error::Error for Diagnostics {}
+
+// エラーが1つだけのとき
+impl From<Diagnostic> for Diagnostics {
+	fn from(d: Diagnostic) -> Self {
+		Diagnostics(vec![d])
+	}
+}
+
+impl From<Box<dyn error::Error>> for Diagnostics {
+	fn from(e: Box<dyn error::Error>) -> Self {
+		match e.downcast::<Diagnostics>() {
+			Ok(d) => *d,
+			Err(e) => Diagnostics::from(Diagnostic::from(e)),
+		}
+	}
+}
+
+// ?でBox<dyn Error>からDiagnosticにする
+// Diagnosticならそのまま、io::Errorなどは位置なしのエラーにする
+impl From<Box<dyn error::Error>> for Diagnostic {
+	fn from(e: Box<dyn error::Error>) -> Self {
+		match e.downcast::<Diagnostic>() {
+			Ok(d) => *d,
+			Err(e) => Diagnostic::without_loc(e.to_string()).with_code(ErrorCode::Driver),
+		}
+	}
+}
+
+// 診断をどの形式で出すか
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagnosticsFormat {
+	// loc: error: message
+	Text,
+	// --diagnostics-format=json
+	Json,
+	// --diagnostics-format=sarif SARIF 2.1.0
+	Sarif,
+}
+
+// clangとおなじ色
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const MAGENTA: &str = "\x1b[1;35m";
+const BLACK: &str = "\x1b[1;30m";
+const GREEN: &str = "\x1b[1;32m";
+const RESET: &str = "\x1b[0m";
+
+// 行番号を出すところの幅
+const GUTTER: &str = "      | ";
+
+// colorならstyleの色をつける
+fn paint(out: &mut String, color: bool, style: &str, text: &str) {
+	if color {
+		out.push_str(style);
+		out.push_str(text);
+		out.push_str(RESET);
+	} else {
+		out.push_str(text);
+	}
+}
+
+// file:1:9: error: message
+//     1 | int a = x;
+//       |         ^
+// 補足もおなじ形でつづけて出す
+fn render_text(out: &mut String, d: &Diagnostic, color: bool) {
+	if let Some(ref loc) = d.loc {
+		paint(out, color, BOLD, &format!("{}:", loc));
+		out.push(' ');
+	}
+	let style = match d.severity {
+		Severity::Error => RED,
+		Severity::Warning => MAGENTA,
+		Severity::Note => BLACK,
+	};
+	paint(out, color, style, &format!("{}:", d.severity));
+	out.push(' ');
+	paint(out, color, BOLD, &d.message_with_flag());
+	out.push('\n');
+
+	if let Some(ref loc) = d.loc {
+		if let Some(source) = read_source(&loc.file) {
+			snippet(out, &source, d, loc, color);
+		}
+	}
+	for note in &d.notes {
+		render_text(out, note, color);
+	}
+}
+
+// 診断の位置の行を出して、その下に ^~~ で下線を引く
+// 同じファイルのfix-itは、挿入する文字列をその位置の下に出す 別の行ならその行も出す
+fn snippet(out: &mut String, source: &str, d: &Diagnostic, loc: &Loc, color: bool) {
+	let Some(line) = source.lines().nth(loc.line - 1) else {
+		return;
+	};
+	out.push_str(&format!("{:>5} | {}\n", loc.line, line));
+	out.push_str(GUTTER);
+	out.push_str(&indent(line, loc.col));
+	// lenはバイト数なので、行の中で何文字になるかを数える
+	let width = line.chars().skip(loc.col - 1).collect::<String>().char_indices().take_while(|(i, _)| *i < d.len).count();
+	let underline = format!("^{}", "~".repeat(width.saturating_sub(1)));
+	paint(out, color, GREEN, &underline);
+	out.push('\n');
+
+	for fixit in d.fixits.iter().filter(|fixit| fixit.loc.file == loc.file) {
+		let Some(fixit_line) = source.lines().nth(fixit.loc.line - 1) else {
+			continue;
+		};
+		if fixit.loc.line != loc.line {
+			out.push_str(&format!("{:>5} | {}\n", fixit.loc.line, fixit_line));
+		}
+		out.push_str(GUTTER);
+		out.push_str(&indent(fixit_line, fixit.loc.col));
+		paint(out, color, GREEN, &fixit.text);
+		out.push('\n');
+	}
+}
+
+// col文字目の下までの空白 タブはタブのままにして位置がずれないようにする
+fn indent(line: &str, col: usize) -> String {
+	line.chars()
+		.chain(std::iter::repeat(' '))
+		.take(col - 1)
+		.map(|c| if c == '\t' { '\t' } else { ' ' })
+		.collect()
+}
+
+// 診断に出すソース 組み込みヘッダは埋め込んだものを使う
+// 標準入力や文字列のソースはもう読めないので出さない
+fn read_source(file: &str) -> Option<String> {
+	match builtin_header(Path::new(file)) {
+		Some(contents) => Some(contents.to_string()),
+		None => fs::read_to_string(file).ok(),
+	}
+}
+
+// {"severity": .., "code": .., "message": .., "primary": {..}, "secondary": [..], "fixits": [..]}
+// primaryは診断の位置、secondaryは補足の位置とメッセージ
+fn diagnostic_json(d: &Diagnostic) -> Json {
+	let code = d.code_name().map_or(Json::Null, Json::Str);
+	let primary = match d.loc {
+		Some(ref loc) => Json::Object(span_fields(loc, d.len)),
+		None => Json::Null,
+	};
+	let secondary = d
+		.notes
+		.iter()
+		.filter_map(|note| {
+			let mut fields = span_fields(note.loc.as_ref()?, note.len);
+			fields.push(("message", Json::str(&note.message)));
+			Some(Json::Object(fields))
+		})
+		.collect();
+	let fixits = d
+		.fixits
+		.iter()
+		.map(|fixit| {
+			let mut fields = span_fields(&fixit.loc, fixit.len);
+			fields.push(("replacement", Json::str(&fixit.text)));
+			Json::Object(fields)
+		})
+		.collect();
+
+	Json::Object(vec![
+		("severity", Json::str(&d.severity.to_string())),
+		("code", code),
+		("message", Json::str(&d.message)),
+		("primary", primary),
+		("secondary", Json::Array(secondary)),
+		("fixits", Json::Array(fixits)),
+	])
+}
+
+// "loc": {..}, "span": {"start": .., "end": ..} --dump-tokens=json とおなじ形
+fn span_fields(loc: &Loc, len: usize) -> Vec<(&'static str, Json)> {
+	vec![
+		("loc", loc_json(loc)),
+		(
+			"span",
+			Json::Object(vec![
+				("start", Json::Int(loc.offset as i64)),
+				("end", Json::Int((loc.offset + len) as i64)),
+			]),
+		),
+	]
+}
+
+// SARIF 2.1.0 のログ
+// https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+// 1回のコンパイルを1つのrunにする 警告のオプションとエラーの種類をruleのidにする
+fn sarif(diagnostics: &[Diagnostic]) -> Json {
+	let mut rules = Vec::new();
+	for code in diagnostics.iter().filter_map(|d| d.code_name()) {
+		if !rules.contains(&code) {
+			rules.push(code);
+		}
+	}
+	let rules = rules.into_iter().map(|code| Json::Object(vec![("id", Json::Str(code))])).collect();
+
+	let results = diagnostics
+		.iter()
+		.map(|d| {
+			let mut fields = Vec::new();
+			if let Some(code) = d.code_name() {
+				fields.push(("ruleId", Json::Str(code)));
+			}
+			fields.push(("level", Json::str(&d.severity.to_string())));
+			fields.push(("message", sarif_message(&d.message)));
+			let locations = d.loc.iter().map(|loc| Json::Object(sarif_location(loc, d.len))).collect();
+			fields.push(("locations", Json::Array(locations)));
+			let related = d
+				.notes
+				.iter()
+				.filter_map(|note| {
+					let mut location = sarif_location(note.loc.as_ref()?, note.len);
+					location.push(("message", sarif_message(&note.message)));
+					Some(Json::Object(location))
+				})
+				.collect();
+			fields.push(("relatedLocations", Json::Array(related)));
+			if !d.fixits.is_empty() {
+				let changes = d
+					.fixits
+					.iter()
+					.map(|fixit| {
+						Json::Object(vec![
+							("artifactLocation", sarif_artifact(&fixit.loc)),
+							(
+								"replacements",
+								Json::Array(vec![Json::Object(vec![
+									("deletedRegion", sarif_region(&fixit.loc, fixit.len)),
+									("insertedContent", Json::Object(vec![("text", Json::str(&fixit.text))])),
+								])]),
+							),
+						])
+					})
+					.collect();
+				fields.push(("fixes", Json::Array(vec![Json::Object(vec![("artifactChanges", Json::Array(changes))])])));
+			}
+			Json::Object(fields)
+		})
+		.collect();
+
+	Json::Object(vec![
+		("$schema", Json::str("https://json.schemastore.org/sarif-2.1.0.json")),
+		("version", Json::str("2.1.0")),
+		(
+			"runs",
+			Json::Array(vec![Json::Object(vec![
+				(
+					"tool",
+					Json::Object(vec![(
+						"driver",
+						Json::Object(vec![
+							("name", Json::str("rust9cc")),
+							("version", Json::str(env!("CARGO_PKG_VERSION"))),
+							("rules", Json::Array(rules)),
+						]),
+					)]),
+				),
+				("results", Json::Array(results)),
+			])]),
+		),
+	])
+}
+
+fn sarif_message(text: &str) -> Json {
+	Json::Object(vec![("text", Json::str(text))])
+}
+
+fn sarif_artifact(loc: &Loc) -> Json {
+	Json::Object(vec![("uri", Json::str(&loc.file))])
+}
+
+fn sarif_location(loc: &Loc, len: usize) -> Vec<(&'static str, Json)> {
+	vec![(
+		"physicalLocation",
+		Json::Object(vec![
+			("artifactLocation", sarif_artifact(loc)),
+			("region", sarif_region(loc, len)),
+		]),
+	)]
+}
+
+fn sarif_region(loc: &Loc, len: usize) -> Json {
+	Json::Object(vec![
+		("startLine", Json::Int(loc.line as i64)),
+		("startColumn", Json::Int(loc.col as i64)),
+		("byteOffset", Json::Int(loc.offset as i64)),
+		("byteLength", Json::Int(len as i64)),
+	])
+}
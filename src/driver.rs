@@ -0,0 +1,167 @@
+use std::env;
+use std::fs;
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+
+use crate::config::{Config, Emit};
+use crate::diagnostic::{Diagnostic, Diagnostics, DiagnosticsFormat, ErrorCode, Severity};
+use crate::{compile, is_c_source, Result};
+
+// gccのドライバーとおなじく、アセンブリを一時ファイルに書いて as でアセンブルし、cc でリンクする
+// リンクはCランタイム(crt1.oなど)の場所を知ってる cc にまかせる
+// 入力が .c ならコンパイルから、.s ならアセンブルから、それ以外はリンクにそのまま渡す
+// 警告はdiagnosticsに足す
+// コンパイルエラーになっても残りの入力は続けて、エラーはリンクの前にまとめて返す
+pub fn build(config: &Config, diagnostics: &mut Diagnostics) -> Result<()> {
+	let mut temps = Temps::new(config.save_temps);
+	let mut objs = Vec::new();
+	let mut errors = Diagnostics::default();
+
+	for input in &config.inputs {
+		let asm_path = if is_c_source(input) {
+			let path = temps.path(input, "s");
+			match compile(input, config, diagnostics) {
+				Ok(asm) => fs::write(&path, asm)?,
+				Err(e) => {
+					errors.0.extend(Diagnostics::from(e).0);
+					continue;
+				}
+			}
+			path
+		} else if Path::new(input).extension().is_some_and(|ext| ext == "s") {
+			PathBuf::from(input)
+		} else {
+			objs.push(PathBuf::from(input));
+			continue;
+		};
+
+		let obj_path = match config.emit {
+			Emit::Obj => output_path(config, input, &mut temps),
+			_ => temps.path(input, "o"),
+		};
+		let mut cmd = Command::new("as");
+		// スタックを実行可能にしない ldの警告が出なくなる
+		cmd.arg("--noexecstack").arg("-o").arg(&obj_path).arg(&asm_path);
+		exec(cmd, "as", "binutils", config, diagnostics)?;
+		if config.emit == Emit::Obj {
+			copy_to_stdout(config, &obj_path)?;
+		}
+		objs.push(obj_path);
+	}
+	if !errors.0.is_empty() {
+		return Err(errors.into());
+	}
+
+	// -c ならリンクしない .oの入力はgccとおなじく使わずに終わる
+	if config.emit == Emit::Obj {
+		return Ok(());
+	}
+
+	// Cのファイルは1つだけなのでmainは重複しない Config::new で確かめてある
+	let exe_path = output_path(config, "", &mut temps);
+	let mut cmd = Command::new("cc");
+	cmd.arg("-o").arg(&exe_path).args(&objs);
+	for dir in &config.lib_paths {
+		cmd.arg(format!("-L{}", dir));
+	}
+	for lib in &config.libs {
+		cmd.arg(format!("-l{}", lib));
+	}
+	exec(cmd, "cc", "gccかclang", config, diagnostics)?;
+	copy_to_stdout(config, &exe_path)
+}
+
+// -o - のときはいったん一時ファイルに出して、あとで標準出力にコピーする
+// 標準出力に出すものは -save-temps でも残さない
+fn output_path(config: &Config, input: &str, temps: &mut Temps) -> PathBuf {
+	match config.output_path(input) {
+		Some(path) if path != "-" => PathBuf::from(path),
+		_ => temps.temp_file("out"),
+	}
+}
+
+fn copy_to_stdout(config: &Config, path: &Path) -> Result<()> {
+	if config.output.as_deref() == Some("-") {
+		io::stdout().write_all(&fs::read(path)?)?;
+	}
+	Ok(())
+}
+
+// コマンドを実行する 見つからなければなにを入れればいいかを出す
+// JSON/SARIFのときは標準エラー出力を1つのドキュメントにするので、コマンドが出したものは診断に入れる
+// 成功していれば警告、失敗していればエラーのメッセージにする
+fn exec(mut cmd: Command, name: &str, package: &str, config: &Config, diagnostics: &mut Diagnostics) -> Result<()> {
+	if config.diagnostics_format != DiagnosticsFormat::Text {
+		cmd.stderr(Stdio::piped());
+	}
+	let output = match cmd.spawn().and_then(|child| child.wait_with_output()) {
+		Ok(output) => output,
+		Err(e) if e.kind() == ErrorKind::NotFound => {
+			return Err(format!("{} が見つかりません ({}をインストールしてください)", name, package).into())
+		}
+		Err(e) => return Err(format!("{} を実行できません: {}", name, e).into()),
+	};
+	let stderr = String::from_utf8_lossy(&output.stderr);
+	let stderr = stderr.trim_end();
+	if !output.status.success() {
+		let mut message = format!("{} が失敗しました ({})", name, output.status);
+		if !stderr.is_empty() {
+			message.push('\n');
+			message.push_str(stderr);
+		}
+		return Err(message.into());
+	}
+	if !stderr.is_empty() {
+		diagnostics.0.push(Diagnostic {
+			severity: Severity::Warning,
+			..Diagnostic::without_loc(format!("{}: {}", name, stderr)).with_code(ErrorCode::Driver)
+		});
+	}
+	Ok(())
+}
+
+// 途中でつくった一時ファイル
+// エラーで抜けたときも消えるようにDropで消す
+struct Temps {
+	paths: Vec<PathBuf>,
+	// 一時ファイルの名前がかぶらないようにつける番号
+	count: usize,
+	// -save-temps なら消さずにカレントディレクトリに残す
+	keep: bool,
+}
+
+impl Temps {
+	fn new(keep: bool) -> Self {
+		Temps {
+			paths: Vec::new(),
+			count: 0,
+			keep,
+		}
+	}
+
+	// inputからつくる一時ファイルのパス
+	fn path(&mut self, input: &str, ext: &str) -> PathBuf {
+		if self.keep {
+			let stem = Path::new(input).file_stem().unwrap_or_default();
+			return Path::new(stem).with_extension(ext);
+		}
+		self.temp_file(ext)
+	}
+
+	// keepにかかわらず、最後に消す一時ディレクトリのファイル
+	fn temp_file(&mut self, ext: &str) -> PathBuf {
+		self.count += 1;
+		let path = env::temp_dir().join(format!("rust9cc-{}-{}.{}", process::id(), self.count, ext));
+		self.paths.push(path.clone());
+		path
+	}
+}
+
+impl Drop for Temps {
+	fn drop(&mut self) {
+		for path in &self.paths {
+			let _ = fs::remove_file(path);
+		}
+	}
+}
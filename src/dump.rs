@@ -0,0 +1,264 @@
+use std::fmt::Write;
+
+use crate::json::Json;
+use crate::parse::{Node, NodeKind, Program};
+use crate::token::{Loc, Token, TokenKind};
+use crate::visit::{walk_children, Visitor};
+
+// --dump-ast / --dump-tokens の出力形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+	// 人が読むためのインデントした木
+	Text,
+	// ツールやスナップショットテスト用
+	Json,
+}
+
+// JSONの形がかわったら上げる
+const SCHEMA_VERSION: i64 = 1;
+
+pub fn dump_ast(program: &Program, format: DumpFormat) -> String {
+	match format {
+		DumpFormat::Text => ast_text(program),
+		DumpFormat::Json => ast_json(program).pretty(),
+	}
+}
+
+pub fn dump_tokens(tokens: &[Token], format: DumpFormat) -> String {
+	match format {
+		DumpFormat::Text => tokens_text(tokens),
+		DumpFormat::Json => tokens_json(tokens).pretty(),
+	}
+}
+
+// 1行に1トークン 位置 種類 綴り
+// 行頭のトークンにはbol、前に空白があるトークンにはspaceをつける
+// a.c:1:1 Return "return" bol
+// a.c:1:8 Num "1" space
+fn tokens_text(tokens: &[Token]) -> String {
+	let mut out = String::new();
+	for token in tokens {
+		write!(out, "{} {} {:?}", token.loc, token_kind_name(&token.kind), token.text).unwrap();
+		if let TokenKind::Invalid(ref msg) = token.kind {
+			write!(out, " ({})", msg).unwrap();
+		}
+		if token.at_bol {
+			out.push_str(" bol");
+		}
+		if token.has_space {
+			out.push_str(" space");
+		}
+		out.push('\n');
+	}
+	out
+}
+
+// {
+//   "version": 1,
+//   "tokens": [{"kind": ..., "text": ..., "loc": {...}, "span": {"start": .., "end": ..}, "at_bol": .., "has_space": ..}]
+// }
+// 数値は "value" と "type"、エラーのトークンは "message" もつける
+fn tokens_json(tokens: &[Token]) -> Json {
+	let tokens = tokens
+		.iter()
+		.map(|token| {
+			let span = token.span();
+			let mut fields = vec![
+				("kind", Json::Str(token_kind_name(&token.kind))),
+				("text", Json::str(&token.text)),
+				("loc", loc_json(&token.loc)),
+				(
+					"span",
+					Json::Object(vec![("start", Json::Int(span.start as i64)), ("end", Json::Int(span.end as i64))]),
+				),
+				("at_bol", Json::Bool(token.at_bol)),
+				("has_space", Json::Bool(token.has_space)),
+			];
+			match token.kind {
+				TokenKind::Num(v, ty) => {
+					fields.push(("value", Json::Int(v)));
+					fields.push(("type", Json::Str(ty.to_string())));
+				}
+				TokenKind::FNum(v, ty) => {
+					fields.push(("value", Json::Float(v)));
+					fields.push(("type", Json::Str(ty.to_string())));
+				}
+				TokenKind::Invalid(ref msg) => fields.push(("message", Json::str(msg))),
+				_ => {}
+			}
+			Json::Object(fields)
+		})
+		.collect();
+	Json::Object(vec![("version", Json::Int(SCHEMA_VERSION)), ("tokens", Json::Array(tokens))])
+}
+
+// Num(1, Int) → Num のように中身を除いた名前
+fn token_kind_name(kind: &TokenKind) -> String {
+	let name = format!("{:?}", kind);
+	match name.find('(') {
+		Some(i) => name[..i].to_string(),
+		None => name,
+	}
+}
+
+// 位置のあとにソースの中のバイトの範囲をつける
+// Program
+//   GVar x.static.0: int = 0x3
+//   Decl: int <a.c:1:5 0..9>
+//     LVar a (rbp-8): int <a.c:1:5 4..5>
+//     Num 1: int <a.c:1:9 8..9>
+fn ast_text(program: &Program) -> String {
+	let mut printer = TreePrinter {
+		out: String::new(),
+		depth: 1,
+	};
+	printer.out.push_str("Program\n");
+	for gvar in &program.gvars {
+		write!(printer.out, "  GVar {}: {}", gvar.label, gvar.ty).unwrap();
+		if let Some(init) = gvar.init {
+			write!(printer.out, " = {:#x}", init).unwrap();
+		}
+		printer.out.push('\n');
+	}
+	printer.visit_program(program);
+	printer.out
+}
+
+struct TreePrinter {
+	out: String,
+	depth: usize,
+}
+
+impl Visitor for TreePrinter {
+	fn visit_node(&mut self, node: &Node) {
+		let label = match node.kind {
+			NodeKind::Num(v) => format!("Num {}", v),
+			NodeKind::FNum(v) => format!("FNum {:?}", v),
+			NodeKind::BinOp(ref op, ..) => format!("BinOp {}", op_str(op)),
+			NodeKind::Assign(..) => "Assign".to_string(),
+			NodeKind::LVar(ref name, offset) => format!("LVar {} (rbp-{})", name, offset),
+			NodeKind::GVar(ref name, ref label) => format!("GVar {} ({})", name, label),
+			NodeKind::Return(_) => "Return".to_string(),
+			NodeKind::Cast(_) => "Cast".to_string(),
+			NodeKind::Decl(..) => "Decl".to_string(),
+		};
+		writeln!(
+			self.out,
+			"{}{}: {} <{} {}..{}>",
+			"  ".repeat(self.depth),
+			label,
+			node.ty,
+			node.loc,
+			node.span.start,
+			node.span.end
+		)
+		.unwrap();
+
+		self.depth += 1;
+		walk_children(self, node);
+		self.depth -= 1;
+	}
+}
+
+// {
+//   "version": 1,
+//   "gvars": [{"label": ..., "type": ..., "init": 3 | null}],
+//   "nodes": [node...]
+// }
+fn ast_json(program: &Program) -> Json {
+	let gvars = program
+		.gvars
+		.iter()
+		.map(|gvar| {
+			Json::Object(vec![
+				("label", Json::str(&gvar.label)),
+				("type", Json::Str(gvar.ty.to_string())),
+				("init", gvar.init.map_or(Json::Null, |v| Json::Int(v as i64))),
+			])
+		})
+		.collect();
+	Json::Object(vec![
+		("version", Json::Int(SCHEMA_VERSION)),
+		("gvars", Json::Array(gvars)),
+		("nodes", Json::Array(program.nodes.iter().map(node_json).collect())),
+	])
+}
+
+// {"kind": ..., "type": ..., "loc": {...}, "span": {"start": .., "end": ..}, 種類ごとのフィールド...}
+fn node_json(node: &Node) -> Json {
+	let mut fields = vec![
+		("kind", Json::str(kind_name(&node.kind))),
+		("type", Json::Str(node.ty.to_string())),
+		("loc", loc_json(&node.loc)),
+		(
+			"span",
+			Json::Object(vec![("start", Json::Int(node.span.start as i64)), ("end", Json::Int(node.span.end as i64))]),
+		),
+	];
+	match node.kind {
+		NodeKind::Num(v) => fields.push(("value", Json::Int(v))),
+		NodeKind::FNum(v) => fields.push(("value", Json::Float(v))),
+		NodeKind::BinOp(ref op, ref lhs, ref rhs) => {
+			fields.push(("op", Json::str(op_str(op))));
+			fields.push(("lhs", node_json(lhs)));
+			fields.push(("rhs", node_json(rhs)));
+		}
+		NodeKind::Assign(ref lhs, ref rhs) => {
+			fields.push(("lhs", node_json(lhs)));
+			fields.push(("rhs", node_json(rhs)));
+		}
+		NodeKind::LVar(ref name, offset) => {
+			fields.push(("name", Json::str(name)));
+			fields.push(("offset", Json::Int(offset as i64)));
+		}
+		NodeKind::GVar(ref name, ref label) => {
+			fields.push(("name", Json::str(name)));
+			fields.push(("label", Json::str(label)));
+		}
+		NodeKind::Return(ref expr) | NodeKind::Cast(ref expr) => fields.push(("expr", node_json(expr))),
+		NodeKind::Decl(ref var, ref init) => {
+			fields.push(("var", node_json(var)));
+			fields.push(("init", init.as_ref().map_or(Json::Null, |init| node_json(init))));
+		}
+	}
+	Json::Object(fields)
+}
+
+pub(crate) fn loc_json(loc: &Loc) -> Json {
+	Json::Object(vec![
+		("file", Json::str(&loc.file)),
+		("line", Json::Int(loc.line as i64)),
+		("col", Json::Int(loc.col as i64)),
+		("offset", Json::Int(loc.offset as i64)),
+	])
+}
+
+fn kind_name(kind: &NodeKind) -> &'static str {
+	match kind {
+		NodeKind::Num(_) => "Num",
+		NodeKind::FNum(_) => "FNum",
+		NodeKind::BinOp(..) => "BinOp",
+		NodeKind::Assign(..) => "Assign",
+		NodeKind::LVar(..) => "LVar",
+		NodeKind::GVar(..) => "GVar",
+		NodeKind::Return(_) => "Return",
+		NodeKind::Cast(_) => "Cast",
+		NodeKind::Decl(..) => "Decl",
+	}
+}
+
+// 二項演算子の綴り
+// > と >= はパースしたときに < と <= にしてある
+fn op_str(op: &TokenKind) -> &'static str {
+	match op {
+		TokenKind::Plus => "+",
+		TokenKind::Minus => "-",
+		TokenKind::Mul => "*",
+		TokenKind::Div => "/",
+		TokenKind::EQ => "==",
+		TokenKind::NEQ => "!=",
+		TokenKind::LE => "<=",
+		TokenKind::LeftAngleBracket => "<",
+		_ => unreachable!("二項演算子ではありません: {:?}", op),
+	}
+}
@@ -0,0 +1,8 @@
+#ifndef __STDARG_H
+#define __STDARG_H
+
+// 本当は構造体の配列だけど、構造体もポインタもないので宣言だけできるように int にしておく
+// 可変長引数の関数定義がまだないので va_start/va_arg/va_end/va_copy は定義していない
+#define va_list int
+
+#endif
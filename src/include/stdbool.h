@@ -0,0 +1,10 @@
+#ifndef __STDBOOL_H
+#define __STDBOOL_H
+
+// _Bool がないので int で代用する
+#define bool int
+#define true 1
+#define false 0
+#define __bool_true_false_are_defined 1
+
+#endif
@@ -0,0 +1,16 @@
+#ifndef __STDDEF_H
+#define __STDDEF_H
+
+// typedefがないのでマクロで型名を定義する
+// longもポインタもないので size_t は32bitのunsignedになっている
+#define size_t unsigned
+#define ptrdiff_t int
+#define wchar_t int
+
+#define NULL 0
+
+// gcc/clangとおなじく組み込みにまかせる
+// 構造体がまだないので、つかうと __builtin_offsetof を読めずにエラーになる
+#define offsetof(type, member) __builtin_offsetof(type, member)
+
+#endif
@@ -0,0 +1,26 @@
+#ifndef __STDINT_H
+#define __STDINT_H
+
+// 整数型は int と unsigned しかないので32bitの型だけ定義する
+// int8_t/int16_t/int64_t や intptr_t はまだ定義できない
+#define int32_t int
+#define uint32_t unsigned
+#define int_least32_t int
+#define uint_least32_t unsigned
+#define int_fast32_t int
+#define uint_fast32_t unsigned
+
+#define INT32_MIN (-2147483647 - 1)
+#define INT32_MAX 2147483647
+#define UINT32_MAX 4294967295u
+#define INT_LEAST32_MIN INT32_MIN
+#define INT_LEAST32_MAX INT32_MAX
+#define UINT_LEAST32_MAX UINT32_MAX
+#define INT_FAST32_MIN INT32_MIN
+#define INT_FAST32_MAX INT32_MAX
+#define UINT_FAST32_MAX UINT32_MAX
+
+#define INT32_C(c) c
+#define UINT32_C(c) c ## u
+
+#endif
@@ -0,0 +1,92 @@
+use std::fmt::Write;
+
+// --dump-ast=json などで出力するJSON
+// 依存するクレートを増やしたくないので自前で組み立てる
+// オブジェクトのキーは入れた順に出力する
+pub enum Json {
+	Null,
+	Bool(bool),
+	Int(i64),
+	Float(f64),
+	Str(String),
+	Array(Vec<Json>),
+	Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+	pub fn str(s: &str) -> Json {
+		Json::Str(s.to_string())
+	}
+
+	// 2スペースでインデントした文字列にする
+	pub fn pretty(&self) -> String {
+		let mut out = String::new();
+		self.write(&mut out, 0);
+		out.push('\n');
+		out
+	}
+
+	fn write(&self, out: &mut String, depth: usize) {
+		match self {
+			Json::Null => out.push_str("null"),
+			Json::Bool(b) => write!(out, "{}", b).unwrap(),
+			Json::Int(v) => write!(out, "{}", v).unwrap(),
+			// JSONにはinfやNaNがないのでnullにする
+			Json::Float(v) if !v.is_finite() => out.push_str("null"),
+			Json::Float(v) => write!(out, "{:?}", v).unwrap(),
+			Json::Str(s) => quote(out, s),
+			Json::Array(items) if items.is_empty() => out.push_str("[]"),
+			Json::Array(items) => {
+				out.push('[');
+				for (i, item) in items.iter().enumerate() {
+					if i > 0 {
+						out.push(',');
+					}
+					newline(out, depth + 1);
+					item.write(out, depth + 1);
+				}
+				newline(out, depth);
+				out.push(']');
+			}
+			Json::Object(fields) if fields.is_empty() => out.push_str("{}"),
+			Json::Object(fields) => {
+				out.push('{');
+				for (i, (key, value)) in fields.iter().enumerate() {
+					if i > 0 {
+						out.push(',');
+					}
+					newline(out, depth + 1);
+					quote(out, key);
+					out.push_str(": ");
+					value.write(out, depth + 1);
+				}
+				newline(out, depth);
+				out.push('}');
+			}
+		}
+	}
+}
+
+fn newline(out: &mut String, depth: usize) {
+	out.push('\n');
+	for _ in 0..depth {
+		out.push_str("  ");
+	}
+}
+
+// 文字列をエスケープして""でかこむ
+fn quote(out: &mut String, s: &str) {
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+}
@@ -1,6 +1,20 @@
+//! Cコンパイラ rust9cc
+//!
+//! コマンドラインからは [`run`] 、文字列のソースをコンパイルするには [`compile_str`] をつかう
+//! [`token`] と [`parse`] はそれぞれ字句解析と構文解析で、トークン列や構文木を直接あつかえる
+//!
+//! ```
+//! use rust9cc::parse::NodeKind;
+//! use rust9cc::{parse_str, Config};
+//!
+//! let program = parse_str("int a = 1; return a + 2;", &Config::default()).unwrap();
+//! assert!(matches!(program.nodes[1].kind, NodeKind::Return(_)));
+//! assert_eq!(program.nodes[1].loc.line, 1);
+//! ```
+
 use std::error;
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{self, prelude::*};
 use std::path::Path;
 
 // modとuseの違いがわからなくなったでござる
@@ -11,11 +25,25 @@ use std::path::Path;
 
 mod codegen;
 mod config;
-mod parse;
-mod token;
+mod diagnostic;
+mod driver;
+mod dump;
+mod json;
+pub mod parse;
+mod preprocess;
+mod sema;
+pub mod token;
+pub mod types;
+pub mod visit;
+mod warning;
 
 // pubをつけるとreexport的なかんじ
-pub use config::Config;
+pub use config::{Config, Emit, MacroDef};
+pub use diagnostic::{Diagnostic, Diagnostics, DiagnosticsFormat, ErrorCode, FixIt, Severity};
+pub use dump::DumpFormat;
+pub use token::Loc;
+pub use warning::Warning;
+use preprocess::Preprocessor;
 use token::Tokenizer;
 
 // 組み込みのエラーはいろいろ存在していて、1関数内に複数エラーの型が存在していると
@@ -25,7 +53,55 @@ use token::Tokenizer;
 // https://doc.rust-jp.rs/rust-by-example-ja/trait/dyn.html
 pub type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
 
-pub fn run(config: &Config) -> Result<()> {
+/// configにしたがってコンパイルする
+/// エラーにならなかった警告などはdiagnosticsに足していく
+/// 標準エラー出力にはなにも出さないので、最後にまとめてrenderする
+/// 入力のどれかがエラーになっても残りの入力はコンパイルして、エラーをまとめてErrで返す
+pub fn run(config: &Config, diagnostics: &mut Diagnostics) -> Result<()> {
+	if config.help {
+		print!("{}", config::USAGE);
+		return Ok(());
+	}
+	if config.version {
+		println!("rust9cc {}", env!("CARGO_PKG_VERSION"));
+		return Ok(());
+	}
+
+	match config.emit {
+		// .sと.oはそのままアセンブル・リンクにまわす
+		Emit::Obj | Emit::Exe => driver::build(config, diagnostics),
+		_ => {
+			let mut errors = Diagnostics::default();
+			for input in &config.inputs {
+				let result = if is_c_source(input) {
+					compile(input, config, diagnostics).and_then(|asm| output(&asm, input, config))
+				} else {
+					Err(format!("{}: Cのファイルではないので -E/-S/--emit では処理できません", input).into())
+				};
+				if let Err(e) = result {
+					errors.0.extend(Diagnostics::from(e).0);
+				}
+			}
+			if errors.0.is_empty() {
+				Ok(())
+			} else {
+				Err(errors.into())
+			}
+		}
+	}
+}
+
+// 拡張子が .c ならCのファイル
+// - は標準入力で、Cのソースとして読む
+pub(crate) fn is_c_source(path: &str) -> bool {
+	path == "-" || Path::new(path).extension().is_some_and(|ext| ext == "c")
+}
+
+// Cのファイルを1つコンパイルする
+// Cの翻訳単位はそれぞれファイル全体がmainの本体になる
+// config.emitのところまで処理して、その結果を文字列で返す
+// 警告はdiagnosticsに足す
+pub(crate) fn compile(path: &str, config: &Config, diagnostics: &mut Diagnostics) -> Result<String> {
 	//
 	// ファイル読み込み
 	//
@@ -37,37 +113,120 @@ pub fn run(config: &Config) -> Result<()> {
 	// 上記のショートカット
 	// 厳密にはエラー時に返す型がちょっと違うみたい
 	// https://doc.rust-jp.rs/book-ja/ch09-02-recoverable-errors-with-result.html#%E3%82%A8%E3%83%A9%E3%83%BC%E5%A7%94%E8%AD%B2%E3%81%AE%E3%82%B7%E3%83%A7%E3%83%BC%E3%83%88%E3%82%AB%E3%83%83%E3%83%88-%E6%BC%94%E7%AE%97%E5%AD%90
-	let mut f = File::open(&config.entry)?;
 	let mut contents = String::new();
-	f.read_to_string(&mut contents)?;
+	let path = if path == "-" {
+		io::stdin().read_to_string(&mut contents)?;
+		"<stdin>"
+	} else {
+		let mut f = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+		f.read_to_string(&mut contents).map_err(|e| format!("{}: {}", path, e))?;
+		path
+	};
 	//println!("contents:{}", contents);
 
-	let mut tokenizer = Tokenizer::new(&contents);
+	let output = compile_source(path, &contents, config)?;
+	diagnostics.0.extend(output.diagnostics.0);
+	Ok(output.asm)
+}
+
+/// メモリ上のソースをコンパイルした結果
+#[derive(Debug)]
+pub struct Output {
+	/// アセンブリ
+	/// options.emitが Tokens/Ast/Source ならそれぞれの出力で、Obj/Exe ならアセンブリ
+	pub asm: String,
+	/// エラーにならなかった警告など
+	pub diagnostics: Diagnostics,
+}
+
+impl Output {
+	fn new(asm: String) -> Self {
+		Output {
+			asm,
+			diagnostics: Diagnostics::default(),
+		}
+	}
+}
+
+/// ファイルを読み書きせずに、文字列のソースをコンパイルする
+/// optionsの入出力のファイルは使わない
+/// "..." のincludeはカレントディレクトリから探す
+pub fn compile_str(source: &str, options: &Config) -> std::result::Result<Output, Diagnostics> {
+	compile_source("<string>", source, options)
+}
+
+/// 文字列のソースをプリプロセスして構文木にする
+/// マクロやincludeはoptionsの -I/-D/-U にしたがって処理する
+/// 宣言されていない変数などの意味解析のエラーもここで返す
+pub fn parse_str(source: &str, options: &Config) -> std::result::Result<parse::Program, Diagnostics> {
+	let tokens = Tokenizer::new("<string>", source).generate();
+	let tokens = Preprocessor::new(options).run("<string>", tokens).map_err(preprocess_error)?;
+	let program = parse::parse(tokens, options.error_limit)?;
+	sema::check(&program, options.error_limit)?;
+	Ok(program)
+}
+
+// ディレクティブのエラーはどれもpreprocessorにする
+fn preprocess_error(e: Box<dyn error::Error>) -> Diagnostic {
+	Diagnostic::from(e).or_code(ErrorCode::Preprocessor)
+}
+
+// pathはエラーの表示につかうファイル名
+// 構文エラーはいくつか見つけてからまとめて返す
+fn compile_source(path: &str, contents: &str, config: &Config) -> std::result::Result<Output, Diagnostics> {
+	let mut tokenizer = Tokenizer::new(path, contents);
 	let tokens = tokenizer.generate();
-	//println!("tokens:{:#?}", tokens);
+	if let Emit::Tokens(format) = config.emit {
+		return Ok(Output::new(dump::dump_tokens(&tokens, format)));
+	}
 
 	//
-	// 構文木作成
+	// プリプロセス
 	//
-	let nodes = parse::parse(tokens);
-	//println!("nodes:{:#?}", nodes);
+	let mut preprocessor = Preprocessor::new(config);
+	let tokens = preprocessor.run(path, tokens).map_err(preprocess_error)?;
+
+	// -E ならCのソースに戻して終わり
+	if config.emit == Emit::Source {
+		return Ok(Output::new(preprocess::to_source(&tokens, preprocessor.line_markers())));
+	}
 
 	//
-	// アセンブリに変換
+	// 構文木作成
 	//
-	let result = codegen::codegen(nodes);
-	//println!("compiled:\n{}", result);
+	let program = parse::parse(tokens, config.error_limit)?;
+	sema::check(&program, config.error_limit)?;
+	// -Werror ならエラーになる
+	let diagnostics = Diagnostics(warning::check(&program, config));
+	if diagnostics.has_errors() {
+		return Err(diagnostics);
+	}
+	if let Emit::Ast(format) = config.emit {
+		return Ok(Output {
+			asm: dump::dump_ast(&program, format),
+			diagnostics,
+		});
+	}
 
 	//
-	// 出力
+	// アセンブリに変換
 	//
-	output(&result, &config)?;
-	Ok(())
+	let result = codegen::codegen(program);
+	//println!("compiled:\n{}", result);
+	Ok(Output {
+		asm: result,
+		diagnostics,
+	})
 }
 
-fn output(s: &str, config: &Config) -> Result<()> {
-	let path = Path::new(&config.dist);
-	let mut file = File::create(&path)?;
-	file.write_all(s.as_bytes())?;
+// 出力先が決まってないか、-o - なら標準出力に出す
+fn output(s: &str, input: &str, config: &Config) -> Result<()> {
+	match config.output_path(input) {
+		Some(path) if path != "-" => {
+			let mut file = File::create(Path::new(&path))?;
+			file.write_all(s.as_bytes())?;
+		}
+		_ => io::stdout().write_all(s.as_bytes())?,
+	}
 	Ok(())
 }
@@ -3,21 +3,29 @@ use std::env;
 use std::process;
 
 use rust9cc::run;
-use rust9cc::Config;
+use rust9cc::{Config, Diagnostics, DiagnosticsFormat};
 
-// cargo run entry dist
+// cargo run -- [オプション] ファイル
+// 使い方は --help
 
 fn main() {
-    // Configパースが必要になったら復活する
-    //let config = Config::new(env::args()).unwrap_or_else(|err| {
-    //    eprintln!("引数のパースに失敗! :{}, 引数:{:?}", err, env::args());
-    //    process::exit(1);
-    //});
-
-    let config = Config::new(env::args());
+    let config = Config::new(env::args()).unwrap_or_else(|err| {
+        // オプションが読めなかったので、出し方はデフォルトのまま
+        let color = Config::default().color_diagnostics();
+        eprint!("{}", Diagnostics::from(err).render(DiagnosticsFormat::Text, color));
+        process::exit(1);
+    });
 
-    if let Err(e) = run(&config) {
-        eprintln!("コンパイルに失敗! \n config:{:#?}\n error:{} \n", config, e);
+    // すべての翻訳単位の警告とエラー、ファイルが読めないようなエラーやリンクのエラーを集めて最後に1回だけ出す
+    // JSON/SARIFなら1つのドキュメントになるので、CIやエディタがそのまま読める
+    let mut diagnostics = Diagnostics::default();
+    let result = run(&config, &mut diagnostics);
+    let failed = result.is_err();
+    if let Err(e) = result {
+        diagnostics.0.extend(Diagnostics::from(e).0);
+    }
+    eprint!("{}", diagnostics.render(config.diagnostics_format, config.color_diagnostics()));
+    if failed {
         process::exit(1);
     }
 }
@@ -27,6 +35,6 @@ fn playground() {
     let chars: Vec<char> = "abc".chars().collect();
     println!("char:{:?}", chars);
     println!("slice:{:?}", &chars[0..2]);
-    let string = &chars[0..2].iter().collect::<String>();
+    let _string = &chars[0..2].iter().collect::<String>();
     assert_eq!(1, 1);
 }
@@ -1,64 +1,200 @@
-use crate::token::{Token, TokenKind};
+//! 構文解析
+//!
+//! プリプロセスしたトークン列から構文木をつくる
+//! 関数定義がないので、ファイル全体をmainの本体の文の並びとして読む
 
+use std::ops::Range;
+
+use crate::diagnostic::{self, Diagnostic, Diagnostics, ErrorCode, FixIt};
+use crate::token::{Loc, Token, TokenKind};
+use crate::types::Type;
+
+// 構文エラーはトークンの位置つきで返す
+// Diagnosticは大きいので、エラーのないときのResultが大きくならないようにBoxにする
+type Result<T> = std::result::Result<T, Box<Diagnostic>>;
+
+/// 構文木のノードの種類
 #[derive(Debug)]
 pub enum NodeKind {
-	Num(i32),
+	/// 整数
+	Num(i64),
+	/// 浮動小数点数
+	FNum(f64),
+	/// 二項演算 演算子 左辺 右辺
+	/// > と >= は左右を入れかえて < と <= にしてある
 	BinOp(TokenKind, Box<Node>, Box<Node>),
 	// BinOpとは区別することにした
+	/// 代入 左辺(LVar/GVar) 右辺
 	Assign(Box<Node>, Box<Node>),
-	LVar(String, usize), // 左辺値 変数名 offsett
+	/// ローカル変数 変数名 RBPからのオフセット
+	LVar(String, usize),
+	/// static/externの変数 変数名 ラベル
+	GVar(String, String),
+	/// return文
 	Return(Box<Node>),
+	/// 型変換 変換先の型はNodeのtyをみる
+	Cast(Box<Node>),
+	/// 変数宣言 変数(LVar/GVar) 初期化式
+	Decl(Box<Node>, Option<Box<Node>>),
 }
 
+/// 構文木のノード
 #[derive(Debug)]
 pub struct Node {
 	pub kind: NodeKind,
+	/// 式の評価結果の型
+	/// 文の場合は特に意味はない
+	pub ty: Type,
+	/// 演算子や変数名など、ノードのもとになったトークンの位置
+	pub loc: Loc,
+	/// loc.fileのソースの中でこのノードの式や文が占めるバイトの範囲 ; はふくまない
+	/// 暗黙の型変換のCastは変換する式とおなじ範囲
+	pub span: Range<usize>,
+	/// 括弧でかこまれていたか -Wparentheses でつかう
+	pub parens: bool,
 }
 
 impl Node {
-	fn new(kind: NodeKind) -> Self {
-		Self { kind }
+	fn new(kind: NodeKind, ty: Type, loc: Loc) -> Self {
+		Self {
+			kind,
+			ty,
+			span: loc.offset..loc.offset,
+			loc,
+			parens: false,
+		}
+	}
+
+	fn new_num(v: i64, ty: Type, loc: Loc) -> Self {
+		Self::new(NodeKind::Num(v), ty, loc)
 	}
 
-	fn new_num(v: i32) -> Self {
-		Self::new(NodeKind::Num(v))
+	fn new_fnum(v: f64, ty: Type, loc: Loc) -> Self {
+		Self::new(NodeKind::FNum(v), ty, loc)
 	}
 
-	fn new_binop(token_kind: TokenKind, lhs: Node, rhs: Node) -> Self {
-		Self::new(NodeKind::BinOp(token_kind, Box::new(lhs), Box::new(rhs)))
+	// 型がちがう場合だけCastでつつむ
+	fn new_cast(node: Node, ty: Type) -> Self {
+		if node.ty == ty {
+			return node;
+		}
+		let loc = node.loc.clone();
+		let span = node.span.clone();
+		Self {
+			span,
+			..Self::new(NodeKind::Cast(Box::new(node)), ty, loc)
+		}
+	}
+
+	fn new_binop(token_kind: TokenKind, lhs: Node, rhs: Node, loc: Loc) -> Self {
+		// 通常の算術型変換で両辺の型をそろえる
+		let ty = Type::common(lhs.ty, rhs.ty);
+		let lhs = Self::new_cast(lhs, ty);
+		let rhs = Self::new_cast(rhs, ty);
+		// 比較の結果はint
+		let ty = match token_kind {
+			TokenKind::EQ | TokenKind::NEQ | TokenKind::LE | TokenKind::LeftAngleBracket => Type::Int,
+			_ => ty,
+		};
+		Self::new(NodeKind::BinOp(token_kind, Box::new(lhs), Box::new(rhs)), ty, loc)
 	}
 
-	fn new_ident(s: String, offset: usize) -> Self {
+	fn new_ident(s: String, offset: usize, ty: Type, loc: Loc) -> Self {
 		// 変数名は1文字で、RBPからのオフセットを文字に応じて固定にしとく
 		// let offset = (c as usize - 'a' as usize + 1) * 8;
 
 		// self
-		Self::new(NodeKind::LVar(s, offset))
+		Self::new(NodeKind::LVar(s, offset), ty, loc)
 	}
 
 	// new_binopと統合するか悩ましい
 	// codegenでassingとbinopを区別したかったのでわけてる
-	fn new_assign(lhs: Node, rhs: Node) -> Self {
-		Self::new(NodeKind::Assign(Box::new(lhs), Box::new(rhs)))
+	fn new_assign(lhs: Node, rhs: Node, loc: Loc) -> Self {
+		// 右辺は左辺の型に変換する
+		let ty = lhs.ty;
+		let rhs = Self::new_cast(rhs, ty);
+		Self::new(NodeKind::Assign(Box::new(lhs), Box::new(rhs)), ty, loc)
+	}
+
+	fn new_return(lhs: Node, loc: Loc) -> Self {
+		// 関数はmainしかないので戻り値はint
+		let lhs = Self::new_cast(lhs, Type::Int);
+		Self::new(NodeKind::Return(Box::new(lhs)), Type::Int, loc)
 	}
 
-	fn new_return(lhs: Node) -> Self {
-		Self::new(NodeKind::Return(Box::new(lhs)))
+	fn new_gvar(s: String, label: String, ty: Type, loc: Loc) -> Self {
+		Self::new(NodeKind::GVar(s, label), ty, loc)
 	}
+
+	// 宣言の位置は変数名のところ
+	fn new_decl(var: Node, init: Option<Node>) -> Self {
+		let ty = var.ty;
+		let loc = var.loc.clone();
+		let init = init.map(|init| Box::new(Self::new_cast(init, ty)));
+		Self::new(NodeKind::Decl(Box::new(var), init), ty, loc)
+	}
+}
+
+// 変数の置き場所
+#[derive(Clone)]
+enum Storage {
+	// スタック上 RBPからのオフセット
+	Local(usize),
+	// static/externの変数 参照するラベル
+	Global(String),
 }
 
-struct LVar {
+struct Var {
 	// 変数名
 	name: String,
-	// RBPからのオフセット
-	offset: usize,
+	// 型
+	ty: Type,
+	// 置き場所
+	storage: Storage,
 }
 
 // newと構造体直接生成どっちがいいんだろうね
-impl LVar {
-	fn new(name: String, offset: usize) -> Self {
-		Self { name, offset }
+impl Var {
+	fn new(name: String, ty: Type, storage: Storage) -> Self {
+		Self { name, ty, storage }
 	}
+
+	// locは変数を参照したところ
+	fn to_node(&self, loc: Loc) -> Node {
+		match self.storage {
+			Storage::Local(offset) => Node::new_ident(self.name.clone(), offset, self.ty, loc),
+			Storage::Global(ref label) => Node::new_gvar(self.name.clone(), label.clone(), self.ty, loc),
+		}
+	}
+}
+
+/// .dataに置く変数
+/// externの変数は他の翻訳単位で定義されるのでここには入らない
+#[derive(Debug)]
+pub struct GVar {
+	/// ラベル
+	pub label: String,
+	/// 型
+	pub ty: Type,
+	/// 初期値のビット列 なければ0埋め
+	pub init: Option<u64>,
+}
+
+// 記憶域クラス指定子
+#[derive(PartialEq)]
+enum StorageClass {
+	Auto,
+	Static,
+	Extern,
+}
+
+/// 構文解析の結果
+#[derive(Debug)]
+pub struct Program {
+	/// mainの本体の文
+	pub nodes: Vec<Node>,
+	/// static変数
+	pub gvars: Vec<GVar>,
 }
 
 struct Parser {
@@ -67,8 +203,12 @@ struct Parser {
 	tokens: Vec<Token>,
 	// 参照するtokenの現在位置
 	pos: usize,
-	// ローカル変数
-	lvars: Vec<LVar>,
+	// 変数 宣言した順
+	vars: Vec<Var>,
+	// ローカル変数でつかったスタックのサイズ
+	stack_size: usize,
+	// static変数
+	gvars: Vec<GVar>,
 }
 
 impl Parser {
@@ -76,7 +216,9 @@ impl Parser {
 		Parser {
 			tokens,
 			pos: 0,
-			lvars: Vec::new(),
+			vars: Vec::new(),
+			stack_size: 0,
+			gvars: Vec::new(),
 		}
 	}
 
@@ -91,148 +233,482 @@ impl Parser {
 	}
 
 	// トークンが期待するkindだったらposをすすめる。
-	// そうでない場合、panic。
-	fn expect(&mut self, expect_token_kind: TokenKind) {
+	// そうでない場合、エラー。
+	fn expect(&mut self, expect_token_kind: TokenKind) -> Result<()> {
 		let current_token = &self.tokens[self.pos];
 		if current_token.kind != expect_token_kind {
-			current_token.bad_token(&format!("{:?} を想定してました。", expect_token_kind));
+			let mut error = current_token.error(format!("{:?} を想定してました。", expect_token_kind)).with_code(ErrorCode::ExpectedToken);
+			if let Some(fixit) = self.insert_after_prev(&expect_token_kind) {
+				error.fixits.push(fixit);
+			}
+			return Err(error.into());
 		}
 		self.pos += 1;
+		Ok(())
+	}
+
+	// ; や ) の書きわすれなら、ひとつ前のトークンの後ろに足せばいい
+	// マクロを展開したトークンは位置がマクロの定義のほうなので提案しない
+	fn insert_after_prev(&self, kind: &TokenKind) -> Option<FixIt> {
+		let text = match kind {
+			TokenKind::SemiColon => ";",
+			TokenKind::RightParen => ")",
+			_ => return None,
+		};
+		let prev = self.tokens[..self.pos].last().filter(|prev| prev.origin.is_none())?;
+		Some(FixIt {
+			loc: Loc {
+				col: prev.loc.col + prev.text.chars().count(),
+				offset: prev.loc.offset + prev.text.len(),
+				..prev.loc.clone()
+			},
+			len: 0,
+			text: text.to_string(),
+		})
+	}
+
+	// 型名のトークンかどうか
+	fn is_typename(&self) -> bool {
+		matches!(
+			self.tokens[self.pos].kind,
+			TokenKind::Int
+				| TokenKind::Signed
+				| TokenKind::Unsigned
+				| TokenKind::Float
+				| TokenKind::Double
+				| TokenKind::Static
+				| TokenKind::Extern
+		)
+	}
+
+	// 新しいローカル変数を登録してNodeを返す
+	fn new_lvar(&mut self, name: String, ty: Type, loc: Loc) -> Node {
+		self.stack_size += 8;
+		let var = Var::new(name, ty, Storage::Local(self.stack_size));
+		let node = var.to_node(loc);
+		self.vars.push(var);
+		node
 	}
 
-	fn stmt(&mut self) -> Node {
-		let node;
-		if self.consume(TokenKind::Return) {
-			node = Node::new_return(self.expr());
+	// static/externの変数を登録してNodeを返す
+	fn new_gvar(&mut self, name: String, label: String, ty: Type, loc: Loc) -> Node {
+		let var = Var::new(name, ty, Storage::Global(label));
+		let node = var.to_node(loc);
+		self.vars.push(var);
+		node
+	}
+
+	// startのトークンからいままで読んだトークンまでをnodeの範囲にする
+	// マクロを展開したトークンは位置が定義のほうなので、前後が逆になったら先頭のトークンだけにする
+	fn with_span(&self, mut node: Node, start: usize) -> Node {
+		let begin = self.tokens[start].loc.offset;
+		let end = self.tokens[self.pos - 1].span().end;
+		node.span = begin..end.max(begin);
+		node
+	}
+
+	fn stmt(&mut self) -> Result<Node> {
+		let start = self.pos;
+		let node = if self.is_typename() {
+			self.declaration()?
+		} else if self.consume(TokenKind::Return) {
+			let loc = self.tokens[self.pos - 1].loc.clone();
+			let node = Node::new_return(self.expr()?, loc);
+			self.with_span(node, start)
 		} else {
-			node = self.expr();
+			self.expr()?
+		};
+		self.expect(TokenKind::SemiColon)?;
+		Ok(node)
+	}
+
+	// declspec = ("static" | "extern" | "int" | "signed" | "unsigned" | "float" | "double")+
+	// int, signed, unsigned, signed int, unsigned int, float, double あたりの組み合わせを受け付ける
+	fn declspec(&mut self) -> Result<(Type, StorageClass)> {
+		let mut storage_class = StorageClass::Auto;
+		let mut int = 0;
+		let mut signed = 0;
+		let mut unsigned = 0;
+		let mut flonum = 0;
+		let mut ty = None;
+		while self.is_typename() {
+			match self.tokens[self.pos].kind {
+				// static/externはどちらかひとつだけ
+				TokenKind::Static | TokenKind::Extern => {
+					if storage_class != StorageClass::Auto {
+						return Err(self.tokens[self.pos].error("記憶域クラス指定子は1つまでです。").with_code(ErrorCode::InvalidDeclaration).into());
+					}
+					storage_class = if self.tokens[self.pos].kind == TokenKind::Static {
+						StorageClass::Static
+					} else {
+						StorageClass::Extern
+					};
+				}
+				TokenKind::Int => int += 1,
+				TokenKind::Signed => signed += 1,
+				TokenKind::Unsigned => unsigned += 1,
+				TokenKind::Float => {
+					flonum += 1;
+					ty = Some(Type::Float);
+				}
+				TokenKind::Double => {
+					flonum += 1;
+					ty = Some(Type::Double);
+				}
+				_ => unreachable!(),
+			}
+			// float/doubleは他と組み合わせられない
+			if int > 1 || signed + unsigned > 1 || flonum > 1 || (flonum > 0 && int + signed + unsigned > 0) {
+				return Err(self.tokens[self.pos].error("型の指定が不正です。").with_code(ErrorCode::InvalidDeclaration).into());
+			}
+			self.pos += 1;
 		}
-		self.expect(TokenKind::SemiColon);
-		node
+
+		let ty = match ty {
+			Some(ty) => ty,
+			None if unsigned > 0 => Type::UInt,
+			None => Type::Int,
+		};
+		Ok((ty, storage_class))
 	}
 
-	fn expr(&mut self) -> Node {
+	// declaration = declspec ident ("=" expr)?
+	fn declaration(&mut self) -> Result<Node> {
+		let start = self.pos;
+		let (ty, storage_class) = self.declspec()?;
+		let ident_token = &self.tokens[self.pos];
+		let loc = ident_token.loc.clone();
+		// 変数の範囲は変数名
+		let name_span = ident_token.span();
+		let name = match ident_token.kind {
+			TokenKind::Ident(ref s) => s.clone(),
+			_ => return Err(ident_token.error(format!("変数名を想定してました。actual: {:?}", ident_token.kind)).with_code(ErrorCode::InvalidDeclaration).into()),
+		};
+		self.pos += 1;
+
+		let init = if self.consume(TokenKind::Assign) {
+			Some(self.assign()?)
+		} else {
+			None
+		};
+
+		let (mut var, init) = match storage_class {
+			StorageClass::Auto => (self.new_lvar(name, ty, loc), init),
+			// staticなローカル変数は.dataに置いて、初期化はプログラムの開始時に一度だけ
+			// 同じ名前のstatic変数があってもいいようにラベルには通し番号をつける
+			StorageClass::Static => {
+				let label = format!("{}.static.{}", name, self.gvars.len());
+				let init = match init.map(|init| eval(&Node::new_cast(init, ty))) {
+					Some(Some(bits)) => Some(bits),
+					Some(None) => return Err(self.tokens[self.pos - 1].error("static変数の初期化式は定数である必要があります。").with_code(ErrorCode::NonConstantInitializer).into()),
+					None => None,
+				};
+				self.gvars.push(GVar {
+					label: label.clone(),
+					ty,
+					init,
+				});
+				(self.new_gvar(name, label, ty, loc), None)
+			}
+			// externは他の翻訳単位で定義されたシンボルをそのまま参照する
+			StorageClass::Extern => {
+				if init.is_some() {
+					return Err(self.tokens[self.pos - 1].error("ブロック内のextern宣言は初期化できません。").with_code(ErrorCode::ExternInitializer).into());
+				}
+				(self.new_gvar(name.clone(), name, ty, loc), None)
+			}
+		};
+		var.span = name_span;
+		Ok(self.with_span(Node::new_decl(var, init), start))
+	}
+
+	fn expr(&mut self) -> Result<Node> {
 		self.assign()
 	}
 
-	fn assign(&mut self) -> Node {
-		let node = self.equality();
+	fn assign(&mut self) -> Result<Node> {
+		let start = self.pos;
+		let node = self.equality()?;
 		if self.consume(TokenKind::Assign) {
-			return Node::new_assign(node, self.assign());
+			// 左辺が変数かどうかは意味解析でみる
+			let loc = self.tokens[self.pos - 1].loc.clone();
+			let node = Node::new_assign(node, self.assign()?, loc);
+			return Ok(self.with_span(node, start));
 		}
-		node
+		Ok(node)
 	}
 
-	fn equality(&mut self) -> Node {
-		let mut node = self.relational();
+	fn equality(&mut self) -> Result<Node> {
+		let start = self.pos;
+		let mut node = self.relational()?;
 		loop {
-			if self.consume(TokenKind::EQ) {
-				node = Node::new_binop(TokenKind::EQ, node, self.relational())
+			let loc = self.tokens[self.pos].loc.clone();
+			node = if self.consume(TokenKind::EQ) {
+				Node::new_binop(TokenKind::EQ, node, self.relational()?, loc)
 			} else if self.consume(TokenKind::NEQ) {
-				node = Node::new_binop(TokenKind::NEQ, node, self.relational())
+				Node::new_binop(TokenKind::NEQ, node, self.relational()?, loc)
 			} else {
-				return node;
-			}
+				return Ok(node);
+			};
+			node = self.with_span(node, start);
 		}
 	}
 
-	fn relational(&mut self) -> Node {
-		let mut node = self.add();
+	fn relational(&mut self) -> Result<Node> {
+		let start = self.pos;
+		let mut node = self.add()?;
 		loop {
-			if self.consume(TokenKind::LE) {
-				node = Node::new_binop(TokenKind::LE, node, self.add())
+			let loc = self.tokens[self.pos].loc.clone();
+			node = if self.consume(TokenKind::LE) {
+				Node::new_binop(TokenKind::LE, node, self.add()?, loc)
 			} else if self.consume(TokenKind::LeftAngleBracket) {
-				node = Node::new_binop(TokenKind::LeftAngleBracket, node, self.add())
+				Node::new_binop(TokenKind::LeftAngleBracket, node, self.add()?, loc)
 			} else if self.consume(TokenKind::RE) {
 				// > → <
-				node = Node::new_binop(TokenKind::LE, self.add(), node)
+				Node::new_binop(TokenKind::LE, self.add()?, node, loc)
 			} else if self.consume(TokenKind::RightAngleBracket) {
 				// >= → <=
-				node = Node::new_binop(TokenKind::LeftAngleBracket, self.add(), node)
+				Node::new_binop(TokenKind::LeftAngleBracket, self.add()?, node, loc)
 			} else {
-				return node;
-			}
+				return Ok(node);
+			};
+			node = self.with_span(node, start);
 		}
 	}
 
-	fn add(&mut self) -> Node {
-		let mut node = self.mul();
+	fn add(&mut self) -> Result<Node> {
+		let start = self.pos;
+		let mut node = self.mul()?;
 		loop {
-			if self.consume(TokenKind::Plus) {
-				node = Node::new_binop(TokenKind::Plus, node, self.mul())
+			let loc = self.tokens[self.pos].loc.clone();
+			node = if self.consume(TokenKind::Plus) {
+				Node::new_binop(TokenKind::Plus, node, self.mul()?, loc)
 			} else if self.consume(TokenKind::Minus) {
-				node = Node::new_binop(TokenKind::Minus, node, self.mul())
+				Node::new_binop(TokenKind::Minus, node, self.mul()?, loc)
 			} else {
-				return node;
-			}
+				return Ok(node);
+			};
+			node = self.with_span(node, start);
 		}
 	}
 
-	fn mul(&mut self) -> Node {
-		let mut node = self.unary();
+	fn mul(&mut self) -> Result<Node> {
+		let start = self.pos;
+		let mut node = self.unary()?;
 
 		loop {
-			if self.consume(TokenKind::Mul) {
-				node = Node::new_binop(TokenKind::Mul, node, self.unary());
+			let loc = self.tokens[self.pos].loc.clone();
+			node = if self.consume(TokenKind::Mul) {
+				Node::new_binop(TokenKind::Mul, node, self.unary()?, loc)
 			} else if self.consume(TokenKind::Div) {
-				node = Node::new_binop(TokenKind::Div, node, self.unary());
+				Node::new_binop(TokenKind::Div, node, self.unary()?, loc)
 			} else {
-				return node;
-			}
+				return Ok(node);
+			};
+			node = self.with_span(node, start);
 		}
 	}
 
 	// 単項目
-	fn unary(&mut self) -> Node {
+	fn unary(&mut self) -> Result<Node> {
+		let start = self.pos;
 		// +xの場合は、ただのxにする
 		if self.consume(TokenKind::Plus) {
-			return self.primary();
+			let node = self.primary()?;
+			return Ok(self.with_span(node, start));
 		}
-		// -xの場合は、0 - xにする
+		// -xの場合は、0 - xにする 0の範囲は - のところ
 		if self.consume(TokenKind::Minus) {
-			return Node::new_binop(TokenKind::Minus, Node::new_num(0), self.primary());
+			let loc = self.tokens[self.pos - 1].loc.clone();
+			let zero = self.with_span(Node::new_num(0, Type::Int, loc.clone()), start);
+			let node = Node::new_binop(TokenKind::Minus, zero, self.primary()?, loc);
+			return Ok(self.with_span(node, start));
 		}
 
 		self.primary()
 	}
 
-	fn primary(&mut self) -> Node {
+	fn primary(&mut self) -> Result<Node> {
+		let start = self.pos;
+		let node = self.primary_node()?;
+		Ok(self.with_span(node, start))
+	}
+
+	fn primary_node(&mut self) -> Result<Node> {
 		let current_token = &self.tokens[self.pos];
 		self.pos += 1;
 		match current_token.kind {
 			// ( がくるのであれば、その後はexprがくるはず
 			TokenKind::LeftParen => {
-				let node = self.expr();
+				let mut node = self.expr()?;
 				// exprの後は )
-				self.expect(TokenKind::RightParen);
-				node
+				self.expect(TokenKind::RightParen)?;
+				node.parens = true;
+				Ok(node)
 			}
 			// https://doc.rust-jp.rs/book-ja/ch18-03-pattern-syntax.html?highlight=ref#ref%E3%81%A8ref-mut%E3%81%A7%E3%83%91%E3%82%BF%E3%83%BC%E3%83%B3%E3%81%AB%E5%8F%82%E7%85%A7%E3%82%92%E7%94%9F%E6%88%90%E3%81%99%E3%82%8B
 			// Stringの場合、matchした値の所有権が移動しないようにrefを利用する
 			TokenKind::Ident(ref s) => {
-				if let Some(lvar) = self.lvars.iter().find(|lvar| lvar.name == *s) {
-					Node::new_ident(s.clone(), lvar.offset)
+				// 同じ名前で宣言されてたら後のほうを優先する
+				let loc = current_token.loc.clone();
+				if let Some(var) = self.vars.iter().rev().find(|var| var.name == *s) {
+					Ok(var.to_node(loc))
 				} else {
-					let offset = self.lvars.len() * 8 + 8;
-					self.lvars.push(LVar::new(s.clone(), offset));
-					Node::new_ident(s.clone(), offset)
+					// 宣言されてない変数はintとして読んでおいて、意味解析でエラーにする
+					let s = s.clone();
+					Ok(self.new_lvar(s, Type::Int, loc))
 				}
 			}
-			TokenKind::Num(v) => Node::new_num(v),
-			_ => current_token.bad_token(&format!("number expected, but actual: {:?}", current_token)),
+			TokenKind::Num(v, ty) => Ok(Node::new_num(v, ty, current_token.loc.clone())),
+			TokenKind::FNum(v, ty) => Ok(Node::new_fnum(v, ty, current_token.loc.clone())),
+			_ => {
+				// エラーから立ち直るときに ; を読みとばしすぎないように、読んでないことにしておく
+				self.pos -= 1;
+				Err(current_token.error(format!("number expected, but actual: {:?}", current_token.kind)).with_code(ErrorCode::ExpectedExpression).into())
+			}
 		}
 	}
+
+	// 構文エラーのあとで、次の文の先頭までトークンを読みとばす
+	// ブロックがまだないので、; のあとかEOFまで
+	fn synchronize(&mut self) {
+		while self.tokens[self.pos].kind != TokenKind::EOF {
+			self.pos += 1;
+			if self.tokens[self.pos - 1].kind == TokenKind::SemiColon {
+				return;
+			}
+		}
+	}
+}
+
+// 定数式を評価して、型にあわせたビット列を返す
+// 定数でなければNone
+fn eval(node: &Node) -> Option<u64> {
+	match node.ty {
+		Type::Int | Type::UInt => eval_int(node).map(|v| v as u32 as u64),
+		Type::Float => eval_float(node).map(|v| (v as f32).to_bits() as u64),
+		Type::Double => eval_float(node).map(|v| v.to_bits()),
+	}
+}
+
+// 整数の定数式を評価する
+// codegenとおなじく、intは符号拡張、unsignedはゼロ拡張した値で計算する
+fn eval_int(node: &Node) -> Option<i64> {
+	let truncate = |v: i64| match node.ty {
+		Type::UInt => v as u32 as i64,
+		_ => v as i32 as i64,
+	};
+
+	match node.kind {
+		NodeKind::Num(v) => Some(v),
+		NodeKind::Cast(ref lhs) if lhs.ty.is_flonum() => eval_float(lhs).map(|v| truncate(v as i64)),
+		NodeKind::Cast(ref lhs) => eval_int(lhs).map(truncate),
+		NodeKind::BinOp(ref op, ref lhs, ref rhs) if lhs.ty.is_flonum() => {
+			let (l, r) = (eval_float(lhs)?, eval_float(rhs)?);
+			let v = match op {
+				TokenKind::EQ => l == r,
+				TokenKind::NEQ => l != r,
+				TokenKind::LE => l <= r,
+				TokenKind::LeftAngleBracket => l < r,
+				_ => return None,
+			};
+			Some(v as i64)
+		}
+		NodeKind::BinOp(ref op, ref lhs, ref rhs) => {
+			let (l, r) = (eval_int(lhs)?, eval_int(rhs)?);
+			let v = match op {
+				TokenKind::Plus => truncate(l.wrapping_add(r)),
+				TokenKind::Minus => truncate(l.wrapping_sub(r)),
+				TokenKind::Mul => truncate(l.wrapping_mul(r)),
+				TokenKind::Div => truncate(l.checked_div(r)?),
+				TokenKind::EQ => (l == r) as i64,
+				TokenKind::NEQ => (l != r) as i64,
+				TokenKind::LE => (l <= r) as i64,
+				TokenKind::LeftAngleBracket => (l < r) as i64,
+				_ => return None,
+			};
+			Some(v)
+		}
+		_ => None,
+	}
 }
 
-pub fn parse(tokens: Vec<Token>) -> Vec<Node> {
+// 浮動小数点数の定数式を評価する
+fn eval_float(node: &Node) -> Option<f64> {
+	let v = match node.kind {
+		NodeKind::FNum(v) => v,
+		NodeKind::Cast(ref lhs) if lhs.ty.is_flonum() => eval_float(lhs)?,
+		NodeKind::Cast(ref lhs) => eval_int(lhs)? as f64,
+		NodeKind::BinOp(ref op, ref lhs, ref rhs) => {
+			let (l, r) = (eval_float(lhs)?, eval_float(rhs)?);
+			match op {
+				TokenKind::Plus => l + r,
+				TokenKind::Minus => l - r,
+				TokenKind::Mul => l * r,
+				TokenKind::Div => l / r,
+				_ => return None,
+			}
+		}
+		_ => return None,
+	};
+	if node.ty == Type::Float {
+		Some(v as f32 as f64)
+	} else {
+		Some(v)
+	}
+}
+
+/// トークン列を構文解析する
+/// トークン列はプリプロセス済みで、最後だけがEOFになっている必要がある そうでなければエラーにする
+/// 構文エラーがあってもその文を読みとばして続け、見つけたエラーをまとめて返す
+/// error_limit個のエラーで打ち切る 0なら打ち切らない
+///
+/// ```
+/// use rust9cc::token::Tokenizer;
+///
+/// let errors = rust9cc::parse::parse(Vec::new(), 20).unwrap_err();
+/// assert_eq!(errors.0.len(), 1);
+///
+/// // EOFが途中にあるトークン列もエラー
+/// let mut tokens = Tokenizer::new("a.c", "1 +;").generate();
+/// tokens.extend(Tokenizer::new("b.c", "2;").generate());
+/// let errors = rust9cc::parse::parse(tokens, 0).unwrap_err();
+/// assert_eq!(errors.0.len(), 1);
+/// ```
+pub fn parse(tokens: Vec<Token>, error_limit: usize) -> std::result::Result<Program, Diagnostics> {
+	// EOFで止まるまで読むので、EOFがないと最後のトークンのさきを読んでしまう
+	if !matches!(tokens.last(), Some(token) if token.kind == TokenKind::EOF) {
+		return Err(Diagnostics(vec![Diagnostic::without_loc("トークン列が EOF で終わっていません").with_code(ErrorCode::Syntax)]));
+	}
+	// 途中のEOFはエラーから立ち直るときに読みとばせないので、同じところで止まってしまう
+	if let Some(eof) = tokens[..tokens.len() - 1].iter().find(|token| token.kind == TokenKind::EOF) {
+		return Err(Diagnostics(vec![Diagnostic::error(&eof.loc, "トークン列の途中に EOF があります").with_code(ErrorCode::Syntax)]));
+	}
 	let mut nodes = Vec::new();
+	let mut errors = Vec::new();
 	let mut parser = Parser::new(tokens);
 
 	// parser内のtokenを走査してく
 	// parser.posは0からはじまるので補正
 	while (parser.tokens.len() - 1) != parser.pos {
-		nodes.push(parser.stmt());
+		match parser.stmt() {
+			Ok(node) => nodes.push(node),
+			Err(e) => {
+				errors.push(e.or_code(ErrorCode::Syntax));
+				if errors.len() == error_limit {
+					errors.push(diagnostic::too_many_errors(error_limit));
+					break;
+				}
+				parser.synchronize();
+			}
+		}
+	}
+	if !errors.is_empty() {
+		return Err(Diagnostics(errors));
 	}
 
-	nodes
+	Ok(Program {
+		nodes,
+		gvars: parser.gvars,
+	})
 }
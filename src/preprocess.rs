@@ -0,0 +1,1302 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::token::{Loc, Token, TokenKind, Tokenizer};
+use crate::types::Type;
+use crate::config::MacroDef;
+use crate::diagnostic::{Diagnostic, ErrorCode};
+use crate::Config;
+use crate::Result;
+
+// 組み込みヘッダ バイナリに埋め込んでおいて <...> のときに最初に探す
+// glibcのヘッダはGNU拡張をつかっていて読めないので、最低限のものを自前で持つ
+const BUILTIN_INCLUDE_DIR: &str = "<rust9cc>";
+const BUILTIN_HEADERS: [(&str, &str); 4] = [
+	("stddef.h", include_str!("include/stddef.h")),
+	("stdarg.h", include_str!("include/stdarg.h")),
+	("stdbool.h", include_str!("include/stdbool.h")),
+	("stdint.h", include_str!("include/stdint.h")),
+];
+
+// <...> のときに最後に探すシステムのインクルードパス
+const SYSTEM_INCLUDE_PATHS: [&str; 3] = ["/usr/local/include", "/usr/include/x86_64-linux-gnu", "/usr/include"];
+
+// マクロ
+struct Macro {
+	// 関数形式マクロなら仮引数 オブジェクト形式ならNone
+	// 可変長引数は最後に __VA_ARGS__ として入れておく
+	params: Option<Vec<String>>,
+	is_variadic: bool,
+	// 置き換えるトークン列
+	body: Vec<Token>,
+	// __LINE__ のように展開するたびに中身がかわるマクロ
+	builtin: Option<fn(&mut Preprocessor, &Token) -> Token>,
+}
+
+// #if/#elif/#else のどこにいるか
+#[derive(PartialEq)]
+enum CondCtx {
+	Then,
+	Elif,
+	Else,
+}
+
+// ネストしている #if
+struct Cond {
+	ctx: CondCtx,
+	// どれかの節をすでに採用したか
+	included: bool,
+	// #if の位置 閉じられてないときのエラー表示用
+	loc: Loc,
+}
+
+/// -E の出力に入れる # 行番号 "ファイル名" フラグ
+/// ファイルに入ったところと、includeから戻ったところで出す
+pub struct LineMarker {
+	// 出力するトークン列の何番目の前に出すか
+	pos: usize,
+	line: usize,
+	file: Rc<str>,
+	// GNUとおなじく 1ならファイルに入った 2ならincludeから戻った
+	flag: Option<u8>,
+}
+
+// トークン列を受け取って、ディレクティブとマクロを処理したトークン列を返す
+pub struct Preprocessor {
+	// -I で指定されたパス
+	include_paths: Vec<PathBuf>,
+	// include中のファイル 循環の検出につかう
+	// (正規化したパス, 見つけたときのパス)
+	stack: Vec<(PathBuf, PathBuf)>,
+	// #pragma once されたファイル
+	once: HashSet<PathBuf>,
+	// include guard で囲まれてたファイルと、そのマクロ名
+	guards: HashMap<PathBuf, String>,
+	// 定義済みのマクロ
+	macros: HashMap<String, Macro>,
+	// -D/-U で指定されたマクロ
+	macro_defs: Vec<String>,
+	// __COUNTER__ の次の値
+	counter: i64,
+	// -E のときにつかう
+	markers: Vec<LineMarker>,
+}
+
+impl Preprocessor {
+	pub fn new(config: &Config) -> Self {
+		Preprocessor {
+			include_paths: config.include_paths.iter().map(PathBuf::from).collect(),
+			stack: Vec::new(),
+			once: HashSet::new(),
+			guards: HashMap::new(),
+			macros: HashMap::new(),
+			macro_defs: config
+				.macros
+				.iter()
+				.map(|def| match def {
+					MacroDef::Define(name, value) => format!("#define {} {}\n", name, value),
+					MacroDef::Undef(name) => format!("#undef {}\n", name),
+				})
+				.collect(),
+			counter: 0,
+			markers: Vec::new(),
+		}
+	}
+
+	/// runで処理したファイルの出入り
+	pub fn line_markers(&self) -> &[LineMarker] {
+		&self.markers
+	}
+
+	// pathはtokensを読んだファイル
+	// 標準入力から読んだときは <stdin> で、"..." のincludeはカレントディレクトリから探す
+	pub fn run(&mut self, path: &str, tokens: Vec<Token>) -> Result<Vec<Token>> {
+		self.define_builtin_macros()?;
+		let canonical = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+		self.stack.push((canonical, PathBuf::from(path)));
+		self.markers.push(LineMarker {
+			pos: 0,
+			line: 1,
+			file: Rc::from(path),
+			flag: None,
+		});
+		let mut out = Vec::new();
+		self.process(tokens, &mut out)?;
+		self.stack.pop();
+		Ok(out)
+	}
+
+	// 定義済みマクロと、-D/-U で指定されたマクロを定義する
+	// #define を書いたソースをプリプロセスして定義する
+	fn define_builtin_macros(&mut self) -> Result<()> {
+		let (date, time) = date_and_time();
+		let mut src = format!(
+			"#define __STDC__ 1
+#define __STDC_VERSION__ 201112
+#define __STDC_HOSTED__ 1
+#define __x86_64__ 1
+#define __x86_64 1
+#define __linux__ 1
+#define __rust9cc__ 1
+#define __DATE__ \"{}\"
+#define __TIME__ \"{}\"
+",
+			date, time
+		);
+		// longがないので __STDC_VERSION__ には L をつけていない
+		for def in self.macro_defs.iter() {
+			src.push_str(def);
+		}
+
+		let tokens = Tokenizer::new("<built-in>", &src).generate();
+		self.process(tokens, &mut Vec::new())?;
+
+		self.add_builtin("__FILE__", file_macro);
+		self.add_builtin("__LINE__", line_macro);
+		self.add_builtin("__COUNTER__", counter_macro);
+		Ok(())
+	}
+
+	fn add_builtin(&mut self, name: &str, f: fn(&mut Preprocessor, &Token) -> Token) {
+		self.macros.insert(
+			name.to_string(),
+			Macro {
+				params: None,
+				is_variadic: false,
+				body: Vec::new(),
+				builtin: Some(f),
+			},
+		);
+	}
+
+	// includeしたファイルのトークンも同じoutに足していく
+	// 出力の何番目でファイルが切り替わったかを -E の行番号に出すため
+	fn process(&mut self, tokens: Vec<Token>, out: &mut Vec<Token>) -> Result<()> {
+		// マクロを展開したトークンは先頭に戻して読み直すのでVecDeque
+		let mut input = VecDeque::from(tokens);
+		// #if はファイルの中で閉じてないといけないので、ファイルごとにもつ
+		let mut conds = Vec::new();
+
+		while let Some(token) = input.pop_front() {
+			// 行頭の#だけがディレクティブ
+			// マクロ展開でできた#はディレクティブにならない
+			if token.kind == TokenKind::Hash && token.at_bol && token.origin.is_none() {
+				let line = read_line(&mut input);
+				if !self.conditional(&token, &line, &mut input, &mut conds)? {
+					self.directive(&token, line, out)?;
+				}
+				continue;
+			}
+
+			if self.expand_macro(&token, &mut input)? {
+				continue;
+			}
+			// トークナイズできなかったものは、読み飛ばされなかったときだけエラーにする
+			if let TokenKind::Invalid(ref msg) = token.kind {
+				return Err(token.error(msg.clone()).with_code(ErrorCode::InvalidToken).into());
+			}
+			out.push(token);
+		}
+
+		if let Some(cond) = conds.last() {
+			return Err(Diagnostic::error(&cond.loc, "#if が閉じられていません").into());
+		}
+		Ok(())
+	}
+
+	// #if/#ifdef/#ifndef/#elif/#else/#endif を処理する
+	// 条件ディレクティブでなければfalse
+	fn conditional(&mut self, hash: &Token, line: &[Token], input: &mut VecDeque<Token>, conds: &mut Vec<Cond>) -> Result<bool> {
+		let name = match line.first().map(|t| &t.kind) {
+			Some(TokenKind::Ident(ref s)) => s.as_str(),
+			_ => return Ok(false),
+		};
+		let args = &line[1..];
+
+		match name {
+			"if" | "ifdef" | "ifndef" => {
+				let val = match name {
+					"if" => self.eval_if(hash, args)?,
+					"ifdef" => self.macros.contains_key(&read_macro_name(hash, args)?),
+					_ => !self.macros.contains_key(&read_macro_name(hash, args)?),
+				};
+				conds.push(Cond {
+					ctx: CondCtx::Then,
+					included: val,
+					loc: hash.loc.clone(),
+				});
+				if !val {
+					skip_cond(input);
+				}
+			}
+			"elif" => {
+				let cond = match conds.last_mut() {
+					Some(cond) if cond.ctx != CondCtx::Else => cond,
+					Some(_) => return Err(Diagnostic::error(&hash.loc, "#else の後に #elif があります").into()),
+					None => return Err(Diagnostic::error(&hash.loc, "対応する #if がない #elif です").into()),
+				};
+				cond.ctx = CondCtx::Elif;
+				// すでにどれかの節を採用してたら、式は評価しない
+				if !cond.included && self.eval_if(hash, args)? {
+					conds.last_mut().unwrap().included = true;
+				} else {
+					skip_cond(input);
+				}
+			}
+			"else" => {
+				let cond = match conds.last_mut() {
+					Some(cond) if cond.ctx != CondCtx::Else => cond,
+					Some(_) => return Err(Diagnostic::error(&hash.loc, "#else が重複しています").into()),
+					None => return Err(Diagnostic::error(&hash.loc, "対応する #if がない #else です").into()),
+				};
+				if let Some(extra) = args.first() {
+					return Err(Diagnostic::error(&extra.loc, "#else の後に余計なトークンがあります").into());
+				}
+				cond.ctx = CondCtx::Else;
+				if cond.included {
+					skip_cond(input);
+				}
+			}
+			"endif" => {
+				if conds.pop().is_none() {
+					return Err(Diagnostic::error(&hash.loc, "対応する #if がない #endif です").into());
+				}
+				if let Some(extra) = args.first() {
+					return Err(Diagnostic::error(&extra.loc, "#endif の後に余計なトークンがあります").into());
+				}
+			}
+			_ => return Ok(false),
+		}
+		Ok(true)
+	}
+
+	// #if/#elif の式を評価する
+	fn eval_if(&mut self, hash: &Token, args: &[Token]) -> Result<bool> {
+		if args.is_empty() {
+			return Err(Diagnostic::error(&hash.loc, "#if の後には式が必要です").into());
+		}
+
+		// defined(X) と defined X はマクロを展開する前に 1/0 に置き換える
+		let mut tokens = Vec::new();
+		let mut i = 0;
+		while i < args.len() {
+			let token = &args[i];
+			if token.kind != TokenKind::Ident("defined".to_string()) {
+				tokens.push(token.clone());
+				i += 1;
+				continue;
+			}
+
+			let has_paren = matches!(args.get(i + 1), Some(t) if t.kind == TokenKind::LeftParen);
+			let name_pos = if has_paren { i + 2 } else { i + 1 };
+			let name = match args.get(name_pos).and_then(macro_name) {
+				Some(name) => name,
+				None => return Err(Diagnostic::error(&token.loc, "defined の後にはマクロ名が必要です").into()),
+			};
+			i = name_pos + 1;
+			if has_paren {
+				match args.get(i) {
+					Some(t) if t.kind == TokenKind::RightParen => i += 1,
+					_ => return Err(Diagnostic::error(&token.loc, "defined( に対応する ) がありません").into()),
+				}
+			}
+			tokens.push(new_num_token(token, self.macros.contains_key(name) as i64));
+		}
+
+		// マクロを展開して、残った識別子は0にする
+		let tokens = self
+			.expand_all(tokens)?
+			.into_iter()
+			.map(|t| if macro_name(&t).is_some() { new_num_token(&t, 0) } else { t })
+			.collect::<Vec<_>>();
+
+		let val = ConstExpr::eval(&tokens, args.last().unwrap())?;
+		Ok(val != 0)
+	}
+
+	// ディレクティブを含まないトークン列のマクロを全部展開する
+	// マクロの実引数の展開につかう
+	fn expand_all(&mut self, tokens: Vec<Token>) -> Result<Vec<Token>> {
+		let mut out = Vec::new();
+		let mut input = VecDeque::from(tokens);
+		while let Some(token) = input.pop_front() {
+			if !self.expand_macro(&token, &mut input)? {
+				out.push(token);
+			}
+		}
+		Ok(out)
+	}
+
+	fn directive(&mut self, hash: &Token, line: Vec<Token>, out: &mut Vec<Token>) -> Result<()> {
+		let name = match line.first() {
+			Some(token) => token,
+			// # だけの行はなにもしない
+			None => return Ok(()),
+		};
+
+		match name.kind {
+			TokenKind::Ident(ref s) if s == "include" => self.include(hash, &line[1..], out),
+			TokenKind::Ident(ref s) if s == "define" => self.define(hash, &line[1..]),
+			TokenKind::Ident(ref s) if s == "undef" => {
+				let name = read_macro_name(hash, &line[1..])?;
+				if let Some(extra) = line.get(2) {
+					return Err(Diagnostic::error(&extra.loc, "#undef の後に余計なトークンがあります").into());
+				}
+				self.macros.remove(&name);
+				Ok(())
+			}
+			TokenKind::Ident(ref s) if s == "pragma" => {
+				// 知らない#pragmaは無視する
+				if let Some(TokenKind::Ident(ref s)) = line.get(1).map(|t| &t.kind) {
+					if s == "once" {
+						let path = self.stack.last().unwrap().0.clone();
+						self.once.insert(path);
+					}
+				}
+				Ok(())
+			}
+			_ => Err(Diagnostic::error(&name.loc, format!("未対応のディレクティブです: #{}", name.text)).into()),
+		}
+	}
+
+	fn include(&mut self, hash: &Token, args: &[Token], out: &mut Vec<Token>) -> Result<()> {
+		let (name, is_quoted) = read_include_name(hash, args)?;
+		let path = match self.search(&name, is_quoted) {
+			Some(path) => path,
+			None => return Err(Diagnostic::error(&hash.loc, format!("{} が見つかりません", name)).into()),
+		};
+		// 組み込みヘッダは実際のファイルがないのでパスをそのままつかう
+		let builtin = builtin_header(&path);
+		let canonical = match builtin {
+			Some(_) => path.clone(),
+			None => fs::canonicalize(&path)
+				.map_err(|e| Diagnostic::error(&hash.loc, format!("{} を読み込めません: {}", path.display(), e)))?,
+		};
+
+		// 2回目以降のincludeを読み飛ばす
+		if self.once.contains(&canonical) {
+			return Ok(());
+		}
+		if let Some(guard) = self.guards.get(&canonical) {
+			if self.macros.contains_key(guard) {
+				return Ok(());
+			}
+		}
+
+		if let Some(i) = self.stack.iter().position(|(p, _)| *p == canonical) {
+			let cycle = self.stack[i..]
+				.iter()
+				.map(|(_, p)| p)
+				.chain(std::iter::once(&path))
+				.map(|p| p.display().to_string())
+				.collect::<Vec<_>>()
+				.join(" -> ");
+			return Err(Diagnostic::error(&hash.loc, format!("#include が循環しています: {}", cycle)).into());
+		}
+
+		let contents = match builtin {
+			Some(contents) => contents.to_string(),
+			None => fs::read_to_string(&path)
+				.map_err(|e| Diagnostic::error(&hash.loc, format!("{} を読み込めません: {}", path.display(), e)))?,
+		};
+		let tokens = Tokenizer::new(&path.to_string_lossy(), &contents).generate();
+
+		// include guard があれば覚えておく
+		if let Some(guard) = detect_include_guard(&tokens) {
+			self.guards.insert(canonical.clone(), guard);
+		}
+
+		self.markers.push(LineMarker {
+			pos: out.len(),
+			line: 1,
+			file: Rc::from(path.to_string_lossy()),
+			flag: Some(1),
+		});
+		self.stack.push((canonical, path));
+		self.process(tokens, out)?;
+		self.stack.pop();
+
+		// 末尾のEOFは除いてつなげる
+		if matches!(out.last(), Some(t) if t.kind == TokenKind::EOF) {
+			out.pop();
+		}
+		// #include の次の行に戻る
+		self.markers.push(LineMarker {
+			pos: out.len(),
+			line: args.last().unwrap_or(hash).loc.line + 1,
+			file: hash.loc.file.clone(),
+			flag: Some(2),
+		});
+		Ok(())
+	}
+
+	// #define NAME body
+	// #define NAME(a, b, ...) body
+	fn define(&mut self, hash: &Token, args: &[Token]) -> Result<()> {
+		let name = read_macro_name(hash, args)?;
+		let rest = &args[1..];
+
+		// 名前の直後に空白なしで ( がくれば関数形式マクロ
+		let is_function = matches!(rest.first(), Some(t) if t.kind == TokenKind::LeftParen && !t.has_space);
+		if !is_function {
+			check_hashhash(rest)?;
+			self.macros.insert(
+				name,
+				Macro {
+					params: None,
+					is_variadic: false,
+					body: rest.to_vec(),
+					builtin: None,
+				},
+			);
+			return Ok(());
+		}
+
+		// 仮引数を読む
+		let mut params = Vec::new();
+		let mut is_variadic = false;
+		let mut i = 1;
+		loop {
+			let token = match rest.get(i) {
+				Some(token) => token,
+				None => return Err(Diagnostic::error(&rest[0].loc, "マクロの仮引数が閉じられていません").into()),
+			};
+			i += 1;
+			match token.kind {
+				TokenKind::RightParen if params.is_empty() => break,
+				TokenKind::Ellipsis => {
+					is_variadic = true;
+					params.push("__VA_ARGS__".to_string());
+					match rest.get(i) {
+						Some(t) if t.kind == TokenKind::RightParen => {
+							i += 1;
+							break;
+						}
+						_ => return Err(Diagnostic::error(&token.loc, "... の後には ) が必要です").into()),
+					}
+				}
+				TokenKind::Ident(ref s) => {
+					if params.contains(s) {
+						return Err(Diagnostic::error(&token.loc, format!("仮引数 {} が重複しています", s)).into());
+					}
+					params.push(s.clone());
+					match rest.get(i).map(|t| &t.kind) {
+						Some(TokenKind::Comma) => i += 1,
+						Some(TokenKind::RightParen) => {
+							i += 1;
+							break;
+						}
+						_ => return Err(Diagnostic::error(&token.loc, "仮引数の後には , か ) が必要です").into()),
+					}
+				}
+				_ => return Err(Diagnostic::error(&token.loc, "仮引数には識別子が必要です").into()),
+			}
+		}
+
+		let body = rest[i..].to_vec();
+		// # の後には仮引数がくる
+		for (j, token) in body.iter().enumerate() {
+			if token.kind == TokenKind::Hash && !matches!(body.get(j + 1).map(|t| &t.kind), Some(TokenKind::Ident(s)) if params.contains(s)) {
+				return Err(Diagnostic::error(&token.loc, "# の後にはマクロの仮引数が必要です").into());
+			}
+		}
+		check_hashhash(&body)?;
+
+		self.macros.insert(
+			name,
+			Macro {
+				params: Some(params),
+				is_variadic,
+				body,
+				builtin: None,
+			},
+		);
+		Ok(())
+	}
+
+	// tokenがマクロなら展開してinputの先頭に戻す
+	// 展開したらtrue
+	fn expand_macro(&mut self, token: &Token, input: &mut VecDeque<Token>) -> Result<bool> {
+		let name = match macro_name(token) {
+			Some(name) => name,
+			None => return Ok(false),
+		};
+		// 展開中のマクロと同じ名前なら展開しない
+		if token.hideset.iter().any(|h| h == name) {
+			return Ok(false);
+		}
+		let mac = match self.macros.get(name) {
+			Some(mac) => mac,
+			None => return Ok(false),
+		};
+
+		if let Some(builtin) = mac.builtin {
+			let t = builtin(self, token);
+			input.push_front(t);
+			return Ok(true);
+		}
+
+		let (mut expanded, hideset) = match mac.params {
+			// オブジェクト形式マクロ
+			// hideset = HS(マクロ名) ∪ {マクロ名}
+			// ## だけ処理する
+			None => {
+				let mut hideset = token.hideset.clone();
+				hideset.push(name.to_string());
+				let body = mac.body.clone();
+				(self.subst(&body, &[])?, hideset)
+			}
+			// 関数形式マクロ
+			// hideset = (HS(マクロ名) ∩ HS(閉じ括弧)) ∪ {マクロ名}
+			Some(ref params) => {
+				// ( が続かなければただの識別子
+				if !matches!(input.front(), Some(t) if t.kind == TokenKind::LeftParen) {
+					return Ok(false);
+				}
+				input.pop_front();
+
+				let params = params.clone();
+				let body = mac.body.clone();
+				let (args, rparen) = read_macro_args(token, input, &params, mac.is_variadic)?;
+				let mut hideset: Vec<String> = token.hideset.iter().filter(|h| rparen.hideset.contains(h)).cloned().collect();
+				hideset.push(name.to_string());
+				let args = params.into_iter().zip(args).collect::<Vec<_>>();
+				(self.subst(&body, &args)?, hideset)
+			}
+		};
+
+		// 展開したトークンには展開元の位置と、展開したマクロの名前を覚えさせておく
+		let origin = token.origin.clone().unwrap_or_else(|| token.loc.clone());
+		for t in expanded.iter_mut() {
+			for h in hideset.iter() {
+				if !t.hideset.contains(h) {
+					t.hideset.push(h.clone());
+				}
+			}
+			t.origin = Some(origin.clone());
+			t.at_bol = false;
+		}
+		if let Some(first) = expanded.first_mut() {
+			first.at_bol = token.at_bol;
+			first.has_space = token.has_space;
+		}
+
+		for t in expanded.into_iter().rev() {
+			input.push_front(t);
+		}
+		Ok(true)
+	}
+
+	// 関数形式マクロの本体の仮引数を実引数で置き換える
+	// オブジェクト形式マクロはargsを空にして ## だけを処理する # はただのトークン
+	fn subst(&mut self, body: &[Token], args: &[(String, Vec<Token>)]) -> Result<Vec<Token>> {
+		let find_arg = |token: &Token| match token.kind {
+			TokenKind::Ident(ref s) => args.iter().find(|(name, _)| name == s).map(|(_, arg)| arg),
+			_ => None,
+		};
+
+		let mut out: Vec<Token> = Vec::new();
+		let mut i = 0;
+		while i < body.len() {
+			let token = &body[i];
+
+			// #x は実引数を文字列にする
+			if token.kind == TokenKind::Hash {
+				if let Some(arg) = body.get(i + 1).and_then(find_arg) {
+					out.push(stringify(token, arg));
+					i += 2;
+					continue;
+				}
+			}
+
+			// x ## y はトークンをつなげる
+			if token.kind == TokenKind::HashHash {
+				let rhs = &body[i + 1];
+				// GNU拡張: , ## __VA_ARGS__ は可変長引数が空ならカンマを消して、空でなければつなげずに後ろに置く
+				let is_va_args = rhs.kind == TokenKind::Ident("__VA_ARGS__".to_string());
+				let after_comma = matches!(out.last(), Some(t) if t.kind == TokenKind::Comma);
+				match find_arg(rhs) {
+					Some(arg) if arg.is_empty() => {
+						if is_va_args && after_comma {
+							out.pop();
+						}
+					}
+					Some(arg) if is_va_args && after_comma => out.extend(arg.iter().cloned()),
+					// 実引数は展開せずに使う
+					Some(arg) => {
+						let lhs = out.pop().unwrap();
+						out.push(paste(&lhs, &arg[0])?);
+						out.extend(arg[1..].iter().cloned());
+					}
+					None => {
+						let lhs = out.pop().unwrap();
+						out.push(paste(&lhs, rhs)?);
+					}
+				}
+				i += 2;
+				continue;
+			}
+
+			if let Some(arg) = find_arg(token) {
+				// x ## y の x も実引数を展開せずに使う
+				if matches!(body.get(i + 1), Some(t) if t.kind == TokenKind::HashHash) {
+					if arg.is_empty() {
+						// 左が空なら右をそのまま使う
+						let rhs = &body[i + 2];
+						match find_arg(rhs) {
+							Some(rhs_arg) => out.extend(rhs_arg.iter().cloned()),
+							None => out.push(rhs.clone()),
+						}
+						i += 3;
+					} else {
+						out.extend(arg.iter().cloned());
+						i += 1;
+					}
+					continue;
+				}
+
+				// それ以外は実引数を先に展開してから置き換える
+				let mut expanded = self.expand_all(arg.clone())?;
+				if let Some(first) = expanded.first_mut() {
+					first.has_space = token.has_space;
+				}
+				out.extend(expanded);
+				i += 1;
+				continue;
+			}
+
+			out.push(token.clone());
+			i += 1;
+		}
+
+		Ok(out)
+	}
+
+	// #include "..." はincludeしてるファイルのディレクトリ、組み込みヘッダ、-I、システムの順に探す
+	// #include <...> は組み込みヘッダ、-I、システムの順
+	fn search(&self, name: &str, is_quoted: bool) -> Option<PathBuf> {
+		if Path::new(name).is_absolute() {
+			return Some(PathBuf::from(name));
+		}
+
+		if is_quoted {
+			if let Some(dir) = self.stack.last().and_then(|(_, p)| p.parent()) {
+				let path = dir.join(name);
+				if path.is_file() {
+					return Some(path);
+				}
+			}
+		}
+
+		if BUILTIN_HEADERS.iter().any(|(header, _)| *header == name) {
+			return Some(Path::new(BUILTIN_INCLUDE_DIR).join(name));
+		}
+
+		self.include_paths
+			.iter()
+			.cloned()
+			.chain(SYSTEM_INCLUDE_PATHS.iter().map(PathBuf::from))
+			.map(|dir| dir.join(name))
+			.find(|path| path.is_file())
+	}
+}
+
+// 組み込みヘッダならその中身
+pub(crate) fn builtin_header(path: &Path) -> Option<&'static str> {
+	let name = path.strip_prefix(BUILTIN_INCLUDE_DIR).ok()?;
+	BUILTIN_HEADERS
+		.iter()
+		.find(|(header, _)| Path::new(header) == name)
+		.map(|(_, contents)| *contents)
+}
+
+// #if の条件が偽のとき、対応する #elif/#else/#endif の手前まで読み飛ばす
+// 読み飛ばすところはディレクティブかどうかだけを見る
+fn skip_cond(input: &mut VecDeque<Token>) {
+	let mut depth = 0;
+	while let Some(token) = input.front() {
+		if token.kind == TokenKind::EOF {
+			return;
+		}
+		match directive_name(token, input.get(1)) {
+			Some("if") | Some("ifdef") | Some("ifndef") => depth += 1,
+			Some("elif") | Some("else") if depth == 0 => return,
+			Some("endif") if depth == 0 => return,
+			Some("endif") => depth -= 1,
+			_ => {}
+		}
+		input.pop_front();
+	}
+}
+
+// __FILE__ 展開したところのファイル名
+fn file_macro(_: &mut Preprocessor, token: &Token) -> Token {
+	let loc = token.origin.as_ref().unwrap_or(&token.loc);
+	new_str_token(token, &loc.file)
+}
+
+// __LINE__ 展開したところの行
+fn line_macro(_: &mut Preprocessor, token: &Token) -> Token {
+	let loc = token.origin.as_ref().unwrap_or(&token.loc);
+	new_num_token(token, loc.line as i64)
+}
+
+// __COUNTER__ 0から展開するたびに1ずつふえる
+fn counter_macro(pp: &mut Preprocessor, token: &Token) -> Token {
+	let val = pp.counter;
+	pp.counter += 1;
+	new_num_token(token, val)
+}
+
+// __DATE__ と __TIME__ の値 "Oct 19 2026" と "12:34:56"
+// タイムゾーンを扱うのがたいへんなのでUTCにしとく
+fn date_and_time() -> (String, String) {
+	const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+	let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as i64;
+	let days = secs.div_euclid(86400);
+	let rem = secs.rem_euclid(86400);
+
+	// 1970-01-01からの日数を年月日にする
+	// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z - era * 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + (month <= 2) as i64;
+
+	let date = format!("{} {:>2} {}", MONTHS[(month - 1) as usize], day, year);
+	let time = format!("{:02}:{:02}:{:02}", rem / 3600, rem % 3600 / 60, rem % 60);
+	(date, time)
+}
+
+// tokenの位置に文字列のトークンを作る
+fn new_str_token(token: &Token, s: &str) -> Token {
+	let mut str = token.clone();
+	str.kind = TokenKind::Str(s.to_string());
+	str.text = quote(s);
+	str
+}
+
+// tokenの位置に整数のトークンを作る
+fn new_num_token(token: &Token, val: i64) -> Token {
+	let mut num = token.clone();
+	num.kind = TokenKind::Num(val, Type::Int);
+	num.text = val.to_string();
+	num
+}
+
+// 行末までのトークンを読む
+fn read_line(input: &mut VecDeque<Token>) -> Vec<Token> {
+	let mut line = Vec::new();
+	while let Some(next) = input.front() {
+		if next.at_bol || next.kind == TokenKind::EOF {
+			break;
+		}
+		line.push(input.pop_front().unwrap());
+	}
+	line
+}
+
+// マクロの名前になれるトークンなら名前を返す
+// int のような予約語もマクロの名前にできる
+fn macro_name(token: &Token) -> Option<&str> {
+	match token.kind {
+		TokenKind::Ident(ref s) => Some(s),
+		TokenKind::Num(..) | TokenKind::FNum(..) | TokenKind::Str(_) => None,
+		_ if token.text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => Some(&token.text),
+		_ => None,
+	}
+}
+
+// #define/#undef のマクロ名を読む
+fn read_macro_name(hash: &Token, args: &[Token]) -> Result<String> {
+	match args.first() {
+		Some(token) => match macro_name(token) {
+			Some(name) => Ok(name.to_string()),
+			None => Err(Diagnostic::error(&token.loc, "マクロ名には識別子が必要です").into()),
+		},
+		None => Err(Diagnostic::error(&hash.loc, "マクロ名が必要です").into()),
+	}
+}
+
+// 関数形式マクロの実引数を読む
+// 返り値は(仮引数ごとの実引数, 閉じ括弧)
+fn read_macro_args(
+	token: &Token,
+	input: &mut VecDeque<Token>,
+	params: &[String],
+	is_variadic: bool,
+) -> Result<(Vec<Vec<Token>>, Token)> {
+	// 可変長引数のぶんはカンマも含めて最後の1つにまとめる
+	let fixed = if is_variadic { params.len() - 1 } else { params.len() };
+	let mut args = Vec::new();
+
+	for i in 0..fixed {
+		if i > 0 {
+			match input.front() {
+				Some(t) if t.kind == TokenKind::Comma => {
+					input.pop_front();
+				}
+				_ => return Err(Diagnostic::error(&token.loc, "マクロの引数が少なすぎます").into()),
+			}
+		}
+		args.push(read_macro_arg(token, input, false)?);
+	}
+
+	if is_variadic {
+		let arg = match input.front() {
+			Some(t) if t.kind == TokenKind::RightParen => Vec::new(),
+			_ => {
+				if fixed > 0 {
+					match input.front() {
+						Some(t) if t.kind == TokenKind::Comma => {
+							input.pop_front();
+						}
+						_ => return Err(Diagnostic::error(&token.loc, "マクロの引数が少なすぎます").into()),
+					}
+				}
+				read_macro_arg(token, input, true)?
+			}
+		};
+		args.push(arg);
+	}
+
+	match input.pop_front() {
+		Some(t) if t.kind == TokenKind::RightParen => Ok((args, t)),
+		_ => Err(Diagnostic::error(&token.loc, "マクロの引数が多すぎます").into()),
+	}
+}
+
+// 実引数を1つ読む
+// 括弧の中のカンマは区切りにしない。read_restなら閉じ括弧までカンマも含めて読む
+fn read_macro_arg(token: &Token, input: &mut VecDeque<Token>, read_rest: bool) -> Result<Vec<Token>> {
+	let mut arg = Vec::new();
+	let mut depth = 0;
+	loop {
+		let next = match input.front() {
+			Some(t) if t.kind != TokenKind::EOF => t,
+			_ => return Err(Diagnostic::error(&token.loc, "マクロの呼び出しが閉じられていません").into()),
+		};
+		match next.kind {
+			TokenKind::RightParen if depth == 0 => break,
+			TokenKind::Comma if depth == 0 && !read_rest => break,
+			TokenKind::LeftParen => depth += 1,
+			TokenKind::RightParen => depth -= 1,
+			_ => {}
+		}
+		arg.push(input.pop_front().unwrap());
+	}
+	Ok(arg)
+}
+
+// #x の結果の文字列リテラルを作る
+fn stringify(hash: &Token, arg: &[Token]) -> Token {
+	let mut s = String::new();
+	for (i, token) in arg.iter().enumerate() {
+		if i > 0 && token.has_space {
+			s.push(' ');
+		}
+		s.push_str(&token.text);
+	}
+
+	new_str_token(hash, &s)
+}
+
+// ## はマクロの先頭と末尾には置けない
+fn check_hashhash(body: &[Token]) -> Result<()> {
+	for token in body.first().into_iter().chain(body.last()) {
+		if token.kind == TokenKind::HashHash {
+			return Err(Diagnostic::error(&token.loc, "## はマクロの先頭と末尾には置けません").into());
+		}
+	}
+	Ok(())
+}
+
+// x ## y の結果のトークンを作る
+// 綴りをつなげてトークナイズしなおす
+fn paste(lhs: &Token, rhs: &Token) -> Result<Token> {
+	let text = format!("{}{}", lhs.text, rhs.text);
+	let mut tokens = Tokenizer::new(&lhs.loc.file, &text).generate();
+	if tokens.len() != 2 {
+		return Err(Diagnostic::error(&lhs.loc, format!("{} と {} をつなげても1つのトークンになりません", lhs.text, rhs.text)).into());
+	}
+
+	let mut token = tokens.remove(0);
+	token.loc = lhs.loc.clone();
+	token.at_bol = lhs.at_bol;
+	token.has_space = lhs.has_space;
+	token.hideset = lhs.hideset.clone();
+	token.origin = lhs.origin.clone();
+	Ok(token)
+}
+
+// #include のファイル名を読む
+// 返り値は(ファイル名, "..."かどうか)
+fn read_include_name(hash: &Token, args: &[Token]) -> Result<(String, bool)> {
+	let first = match args.first() {
+		Some(token) => token,
+		None => return Err(Diagnostic::error(&hash.loc, "#include の後にはファイル名が必要です").into()),
+	};
+
+	match first.kind {
+		TokenKind::Str(ref s) => {
+			if let Some(extra) = args.get(1) {
+				return Err(Diagnostic::error(&extra.loc, "#include の後に余計なトークンがあります").into());
+			}
+			Ok((s.clone(), true))
+		}
+		// <stdio.h> は < stdio . h > にトークナイズされてるので、綴りをつなげる
+		TokenKind::LeftAngleBracket => {
+			let mut name = String::new();
+			for (i, token) in args.iter().enumerate().skip(1) {
+				if token.kind == TokenKind::RightAngleBracket {
+					if let Some(extra) = args.get(i + 1) {
+						return Err(Diagnostic::error(&extra.loc, "#include の後に余計なトークンがあります").into());
+					}
+					return Ok((name, false));
+				}
+				if token.has_space && !name.is_empty() {
+					name.push(' ');
+				}
+				name.push_str(&token.text);
+			}
+			Err(Diagnostic::error(&first.loc, "> がありません").into())
+		}
+		_ => Err(Diagnostic::error(&first.loc, "#include の後にはファイル名が必要です").into()),
+	}
+}
+
+// -E の出力
+// プリプロセスしたトークン列をCのソースに戻す
+// ファイルに入ったところと戻ったところ、行が大きくとぶところには # 行番号 "ファイル名" を出す
+// 出入りにはGNUとおなじく 1/2 のフラグをつける
+pub fn to_source(tokens: &[Token], markers: &[LineMarker]) -> String {
+	let mut out = String::new();
+	let mut file: Option<&Rc<str>> = None;
+	let mut line = 0;
+	let mut markers = markers.iter().peekable();
+
+	for (i, token) in tokens.iter().enumerate() {
+		while let Some(marker) = markers.next_if(|m| m.pos <= i) {
+			if !out.is_empty() && !out.ends_with('\n') {
+				out.push('\n');
+			}
+			out.push_str(&format!("# {} {}", marker.line, quote(&marker.file)));
+			if let Some(flag) = marker.flag {
+				out.push_str(&format!(" {}", flag));
+			}
+			out.push('\n');
+			file = Some(&marker.file);
+			line = marker.line;
+		}
+
+		if token.kind == TokenKind::EOF {
+			break;
+		}
+
+		// マクロを展開したトークンは展開したところの行に出す
+		let loc = token.origin.as_ref().unwrap_or(&token.loc);
+
+		if file != Some(&loc.file) {
+			if !out.is_empty() && !out.ends_with('\n') {
+				out.push('\n');
+			}
+			out.push_str(&format!("# {} {}\n", loc.line, quote(&loc.file)));
+			file = Some(&loc.file);
+			line = loc.line;
+		} else if token.at_bol && loc.line > line {
+			// 数行なら改行でうめて、それ以上とぶなら行番号を出す
+			if loc.line - line <= 8 {
+				for _ in line..loc.line {
+					out.push('\n');
+				}
+			} else {
+				out.push_str(&format!("\n# {} {}\n", loc.line, quote(&loc.file)));
+			}
+			line = loc.line;
+		} else if token.has_space && !out.ends_with('\n') {
+			out.push(' ');
+		}
+
+		out.push_str(&token.text);
+	}
+
+	if !out.ends_with('\n') {
+		out.push('\n');
+	}
+	out
+}
+
+// ファイル名を "..." でかこむ
+fn quote(s: &str) -> String {
+	let mut text = String::from("\"");
+	for c in s.chars() {
+		if c == '"' || c == '\\' {
+			text.push('\\');
+		}
+		text.push(c);
+	}
+	text.push('"');
+	text
+}
+
+// hashが行頭の#で、同じ行に識別子が続いていればディレクティブの名前を返す
+fn directive_name<'a>(hash: &Token, next: Option<&'a Token>) -> Option<&'a str> {
+	if hash.kind != TokenKind::Hash || !hash.at_bol || hash.origin.is_some() {
+		return None;
+	}
+	match next {
+		Some(Token {
+			kind: TokenKind::Ident(ref s),
+			at_bol: false,
+			..
+		}) => Some(s),
+		_ => None,
+	}
+}
+
+// ファイル全体が
+//   #ifndef X
+//   #define X
+//   ...
+//   #endif
+// で囲まれていたらXを返す
+// 2回目以降のincludeで、Xが定義済みならファイルを読まずに済ませるのにつかう
+fn detect_include_guard(tokens: &[Token]) -> Option<String> {
+	if directive_name(tokens.first()?, tokens.get(1)) != Some("ifndef")
+		|| directive_name(tokens.get(3)?, tokens.get(4)) != Some("define")
+	{
+		return None;
+	}
+	let guard = match (&tokens.get(2)?.kind, &tokens.get(5)?.kind) {
+		(TokenKind::Ident(a), TokenKind::Ident(b)) if a == b => a.clone(),
+		_ => return None,
+	};
+
+	// 先頭の #ifndef に対応する #endif がファイルの最後にあること
+	let mut depth = 0;
+	for i in 0..tokens.len() {
+		match directive_name(&tokens[i], tokens.get(i + 1)) {
+			Some("if") | Some("ifdef") | Some("ifndef") => depth += 1,
+			Some("endif") => {
+				depth -= 1;
+				if depth == 0 {
+					return match tokens.get(i + 2) {
+						Some(t) if t.kind == TokenKind::EOF => Some(guard),
+						_ => None,
+					};
+				}
+			}
+			_ => {}
+		}
+	}
+	None
+}
+
+// #if の定数式を評価する
+// 識別子は0に置き換え済みのトークン列を受け取る
+struct ConstExpr<'a> {
+	tokens: &'a [Token],
+	pos: usize,
+	// 式の最後のトークン エラー表示用
+	last: &'a Token,
+}
+
+impl<'a> ConstExpr<'a> {
+	fn eval(tokens: &'a [Token], last: &'a Token) -> Result<i64> {
+		let mut expr = ConstExpr { tokens, pos: 0, last };
+		let val = expr.conditional()?;
+		if let Some(extra) = expr.tokens.get(expr.pos) {
+			return Err(Diagnostic::error(&extra.loc, format!("#if の式に余計なトークンがあります: {}", extra.text)).into());
+		}
+		Ok(val)
+	}
+
+	fn consume(&mut self, kind: TokenKind) -> bool {
+		match self.tokens.get(self.pos) {
+			Some(t) if t.kind == kind => {
+				self.pos += 1;
+				true
+			}
+			_ => false,
+		}
+	}
+
+	fn expect(&mut self, kind: TokenKind) -> Result<()> {
+		if self.consume(kind.clone()) {
+			return Ok(());
+		}
+		let token = self.tokens.get(self.pos).unwrap_or(self.last);
+		Err(Diagnostic::error(&token.loc, format!("#if の式に {:?} が必要です", kind)).into())
+	}
+
+	// conditional = logor ("?" conditional ":" conditional)?
+	fn conditional(&mut self) -> Result<i64> {
+		let cond = self.logor()?;
+		if !self.consume(TokenKind::Question) {
+			return Ok(cond);
+		}
+		let then = self.conditional()?;
+		self.expect(TokenKind::Colon)?;
+		let els = self.conditional()?;
+		Ok(if cond != 0 { then } else { els })
+	}
+
+	fn logor(&mut self) -> Result<i64> {
+		let mut val = self.logand()?;
+		while self.consume(TokenKind::OrOr) {
+			let rhs = self.logand()?;
+			val = (val != 0 || rhs != 0) as i64;
+		}
+		Ok(val)
+	}
+
+	fn logand(&mut self) -> Result<i64> {
+		let mut val = self.bitor()?;
+		while self.consume(TokenKind::AndAnd) {
+			let rhs = self.bitor()?;
+			val = (val != 0 && rhs != 0) as i64;
+		}
+		Ok(val)
+	}
+
+	fn bitor(&mut self) -> Result<i64> {
+		let mut val = self.bitxor()?;
+		while self.consume(TokenKind::Pipe) {
+			val |= self.bitxor()?;
+		}
+		Ok(val)
+	}
+
+	fn bitxor(&mut self) -> Result<i64> {
+		let mut val = self.bitand()?;
+		while self.consume(TokenKind::Caret) {
+			val ^= self.bitand()?;
+		}
+		Ok(val)
+	}
+
+	fn bitand(&mut self) -> Result<i64> {
+		let mut val = self.equality()?;
+		while self.consume(TokenKind::Amp) {
+			val &= self.equality()?;
+		}
+		Ok(val)
+	}
+
+	fn equality(&mut self) -> Result<i64> {
+		let mut val = self.relational()?;
+		loop {
+			if self.consume(TokenKind::EQ) {
+				val = (val == self.relational()?) as i64;
+			} else if self.consume(TokenKind::NEQ) {
+				val = (val != self.relational()?) as i64;
+			} else {
+				return Ok(val);
+			}
+		}
+	}
+
+	fn relational(&mut self) -> Result<i64> {
+		let mut val = self.shift()?;
+		loop {
+			if self.consume(TokenKind::LeftAngleBracket) {
+				val = (val < self.shift()?) as i64;
+			} else if self.consume(TokenKind::LE) {
+				val = (val <= self.shift()?) as i64;
+			} else if self.consume(TokenKind::RightAngleBracket) {
+				val = (val > self.shift()?) as i64;
+			} else if self.consume(TokenKind::RE) {
+				val = (val >= self.shift()?) as i64;
+			} else {
+				return Ok(val);
+			}
+		}
+	}
+
+	fn shift(&mut self) -> Result<i64> {
+		let mut val = self.add()?;
+		loop {
+			if self.consume(TokenKind::Shl) {
+				val = val.wrapping_shl(self.add()? as u32);
+			} else if self.consume(TokenKind::Shr) {
+				val = val.wrapping_shr(self.add()? as u32);
+			} else {
+				return Ok(val);
+			}
+		}
+	}
+
+	fn add(&mut self) -> Result<i64> {
+		let mut val = self.mul()?;
+		loop {
+			if self.consume(TokenKind::Plus) {
+				val = val.wrapping_add(self.mul()?);
+			} else if self.consume(TokenKind::Minus) {
+				val = val.wrapping_sub(self.mul()?);
+			} else {
+				return Ok(val);
+			}
+		}
+	}
+
+	fn mul(&mut self) -> Result<i64> {
+		let mut val = self.unary()?;
+		loop {
+			let op = match self.tokens.get(self.pos) {
+				Some(t) if matches!(t.kind, TokenKind::Mul | TokenKind::Div | TokenKind::Mod) => t,
+				_ => return Ok(val),
+			};
+			self.pos += 1;
+			let rhs = self.unary()?;
+			if op.kind != TokenKind::Mul && rhs == 0 {
+				return Err(Diagnostic::error(&op.loc, "#if の式で0除算しています").into());
+			}
+			val = match op.kind {
+				TokenKind::Mul => val.wrapping_mul(rhs),
+				TokenKind::Div => val.wrapping_div(rhs),
+				_ => val.wrapping_rem(rhs),
+			};
+		}
+	}
+
+	fn unary(&mut self) -> Result<i64> {
+		if self.consume(TokenKind::Plus) {
+			return self.unary();
+		}
+		if self.consume(TokenKind::Minus) {
+			return Ok(self.unary()?.wrapping_neg());
+		}
+		if self.consume(TokenKind::Not) {
+			return Ok((self.unary()? == 0) as i64);
+		}
+		if self.consume(TokenKind::Tilde) {
+			return Ok(!self.unary()?);
+		}
+		self.primary()
+	}
+
+	fn primary(&mut self) -> Result<i64> {
+		let token = match self.tokens.get(self.pos) {
+			Some(token) => token,
+			None => return Err(Diagnostic::error(&self.last.loc, "#if の式が途中で終わっています").into()),
+		};
+		self.pos += 1;
+		match token.kind {
+			TokenKind::LeftParen => {
+				let val = self.conditional()?;
+				self.expect(TokenKind::RightParen)?;
+				Ok(val)
+			}
+			TokenKind::Num(v, _) => Ok(v),
+			_ => Err(Diagnostic::error(&token.loc, format!("#if の式に使えないトークンです: {}", token.text)).into()),
+		}
+	}
+}
@@ -0,0 +1,131 @@
+// 意味解析
+// 構文解析のあとに構文木をたどって、文法としては正しいけどCとしてはおかしいところを見つける
+// 構文解析は宣言されてない変数もintとして読んでおいて、ここでエラーにする
+
+use crate::diagnostic::{self, Diagnostic, Diagnostics, ErrorCode};
+use crate::parse::{Node, NodeKind, Program};
+use crate::token::Loc;
+use crate::types::Type;
+use crate::visit::{self, Visitor};
+
+// 宣言した変数
+struct Declared {
+	name: String,
+	ty: Type,
+	// externなら同じ型で何度宣言してもいい
+	is_extern: bool,
+	// 宣言した変数名の位置
+	loc: Loc,
+}
+
+struct Checker<'a> {
+	program: &'a Program,
+	// ブロックがないので、スコープはmainの本体ひとつだけ
+	scope: Vec<Declared>,
+	errors: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+	fn lookup(&self, name: &str) -> Option<&Declared> {
+		self.scope.iter().rev().find(|d| d.name == name)
+	}
+
+	// externの変数は.dataに置かないのでgvarsに入っていない
+	fn is_extern(&self, label: &str) -> bool {
+		!self.program.gvars.iter().any(|gvar| gvar.label == label)
+	}
+
+	fn declare(&mut self, var: &Node) {
+		let (name, is_extern) = match var.kind {
+			NodeKind::LVar(ref name, _) => (name, false),
+			NodeKind::GVar(ref name, ref label) => (name, self.is_extern(label)),
+			_ => unreachable!("宣言されたのが変数ではありません"),
+		};
+
+		if let Some(prev) = self.lookup(name) {
+			let (code, message) = if prev.is_extern && is_extern {
+				if prev.ty == var.ty {
+					return;
+				}
+				(ErrorCode::ConflictingTypes, format!("{} の型 {} が前の宣言の型 {} と一致しません。", name, var.ty, prev.ty))
+			} else {
+				(ErrorCode::Redeclaration, format!("{} はすでに宣言されています。", name))
+			};
+			let mut error = Diagnostic::error(&var.loc, message).with_len(name.len()).with_code(code);
+			error.notes.push(Diagnostic::note(&prev.loc, "前の宣言はここです").with_len(prev.name.len()));
+			self.errors.push(error);
+			return;
+		}
+
+		self.scope.push(Declared {
+			name: name.clone(),
+			ty: var.ty,
+			is_extern,
+			loc: var.loc.clone(),
+		});
+	}
+
+	fn check_use(&mut self, node: &Node) {
+		let name = match node.kind {
+			NodeKind::LVar(ref name, _) | NodeKind::GVar(ref name, _) => name,
+			_ => unreachable!(),
+		};
+		if self.lookup(name).is_none() {
+			self.errors
+				.push(Diagnostic::error(&node.loc, format!("{} は宣言されていません。", name)).with_len(name.len()).with_code(ErrorCode::UndeclaredIdentifier));
+			// 同じ変数で何度もエラーを出さないように、ここで宣言したことにしておく
+			self.declare(node);
+		}
+	}
+}
+
+impl Visitor for Checker<'_> {
+	// 変数のスコープは宣言のすぐあとからなので、初期化式より先に宣言する
+	fn visit_decl(&mut self, node: &Node) {
+		if let NodeKind::Decl(ref var, ref init) = node.kind {
+			self.declare(var);
+			if let Some(init) = init {
+				self.visit_node(init);
+			}
+		}
+	}
+
+	fn visit_lvar(&mut self, node: &Node) {
+		self.check_use(node);
+	}
+
+	fn visit_gvar(&mut self, node: &Node) {
+		self.check_use(node);
+	}
+
+	fn visit_assign(&mut self, node: &Node) {
+		if let NodeKind::Assign(ref lhs, _) = node.kind {
+			// 代入できるのは変数だけ
+			if !matches!(lhs.kind, NodeKind::LVar(..) | NodeKind::GVar(..)) {
+				self.errors.push(Diagnostic::error(&node.loc, "代入の左辺が変数ではありません。").with_len(1).with_code(ErrorCode::NotAssignable));
+			}
+		}
+		visit::walk_children(self, node);
+	}
+}
+
+// 意味解析のエラーをまとめて返す
+// error_limit個をこえたら、そこまでにして打ち切ったことを足す
+pub fn check(program: &Program, error_limit: usize) -> Result<(), Diagnostics> {
+	let mut checker = Checker {
+		program,
+		scope: Vec::new(),
+		errors: Vec::new(),
+	};
+	checker.visit_program(program);
+
+	let mut errors = checker.errors;
+	if errors.is_empty() {
+		return Ok(());
+	}
+	if error_limit != 0 && errors.len() >= error_limit {
+		errors.truncate(error_limit);
+		errors.push(diagnostic::too_many_errors(error_limit));
+	}
+	Err(Diagnostics(errors))
+}
@@ -1,3 +1,3 @@
-a = 1;
-b = 1;
-return a + b;
\ No newline at end of file
+int a = 1;
+int b = 1;
+return a + b;
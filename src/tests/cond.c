@@ -0,0 +1,31 @@
+#define A 2
+#define B
+int r = 0;
+#if A == 2 && defined(B) && !defined C
+r = r + 1;
+#elif 1
+r = r + 100;
+#else
+r = r + 100;
+#endif
+#ifdef C
+r = r + 100;
+#elif (A << 2) == 8 ? UNKNOWN_IDENT + 1 : 0
+r = r + 2;
+#endif
+#ifndef A
+r = r + 100;
+#else
+#if 0
+this isn't lexed @ as C 99999999999999
+#if 1
+r = r + 100;
+#endif
+#else
+r = r + 4;
+#endif
+#endif
+#if (7 % 4) * -1 == ~2 || 0
+r = r + 8;
+#endif
+return r;
@@ -0,0 +1,4 @@
+int n = 0;
+#include "../include/twice.h"
+#include "../include/twice.h"
+return n;
@@ -0,0 +1,11 @@
+# 1 "src/tests/dump/twice.c"
+int n = 0;
+# 1 "src/tests/dump/../include/twice.h" 1
+
+n = n + 1;
+# 3 "src/tests/dump/twice.c" 2
+# 1 "src/tests/dump/../include/twice.h" 1
+
+n = n + 1;
+# 4 "src/tests/dump/twice.c" 2
+return n;
@@ -0,0 +1,4 @@
+#if 0
+#else 0
+#endif
+return 0;
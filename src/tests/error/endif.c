@@ -0,0 +1,3 @@
+#ifdef FOO
+#endif FOO
+return 0;
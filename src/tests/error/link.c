@@ -0,0 +1,4 @@
+// リンクのときに定義が見つからない
+extern int undefined_in_any_file;
+int unused;
+return undefined_in_any_file;
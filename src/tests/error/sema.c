@@ -0,0 +1,9 @@
+// 宣言されていない変数、再宣言、externの型の不一致、変数でない左辺
+int a = x;
+int a;
+extern int e;
+extern float e;
+extern int e;
+1 = a;
+a + 1 = 2;
+return y + y;
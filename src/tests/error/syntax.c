@@ -0,0 +1,5 @@
+// 構文エラーのある文を読みとばして、残りのエラーも出す
+int a = 1 +;
+b = (2;
+a = 3;
+return a * ;
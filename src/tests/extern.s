@@ -0,0 +1,4 @@
+.data
+.globl ext
+ext:
+  .long 10
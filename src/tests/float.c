@@ -0,0 +1,14 @@
+double x = 1.5;
+float y = 0.25f;
+double z = x * 4 + y;
+int a = z;
+int b = 0x1.8p1 == 3.0;
+int c = 1e2 == 100;
+int d = .5 < 0.75;
+int e = 7 / 2.0 == 3.5;
+unsigned u = 4294967295;
+double w = u;
+int f = w > 0;
+float t = 0.1;
+int g = t == 0.1f;
+return a * 10 + b + c + d + e + f + g;
@@ -0,0 +1,6 @@
+int a = 0;
+#include "include/incr.h"
+#include "include/incr.h"
+#include <b.h>
+#include <b.h>
+return a + b;
@@ -0,0 +1,5 @@
+#ifndef INCR_H
+#define INCR_H
+// 2回includeしても1回だけ足される
+a = a + 1;
+#endif
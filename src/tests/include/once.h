@@ -0,0 +1,2 @@
+#pragma once
+a = a + 10;
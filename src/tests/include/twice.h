@@ -0,0 +1,2 @@
+// include guard がないので、includeするたびに読まれる
+n = n + 1;
@@ -0,0 +1,20 @@
+#define ONE 1
+#define TWO (ONE + ONE)
+#define ADD(a, b) ((a) + (b))
+#define SQ(x) ((x) * (x))
+#define CAT(a, b) a ## b
+#define CALL(f, ...) f(__VA_ARGS__)
+#define OPT(a, ...) ADD(a, 0, ## __VA_ARGS__)
+#define XY x ## y
+#define SECOND(a, b, ...) b
+#define OR9(...) SECOND(9, ## __VA_ARGS__, 9)
+int foo = 2;
+int xy = 3;
+int r = ADD(TWO, SQ(3)) + CAT(x, y);
+r = r + CALL(ADD, 1, 2) + OPT(1);
+#define foo foo + 1
+int f = foo;
+int g = XY + OR9() * 2 + OR9(4, 5) * 4;
+#undef ONE
+int ONE = 100;
+return r + f + ONE / 50 + g;
@@ -0,0 +1,11 @@
+int r = 0;
+#if defined(__rust9cc__) && __STDC__ && __STDC_VERSION__ >= 201112 && __x86_64__
+r = r + 1;
+#endif
+r = r + __LINE__;
+r = r + __COUNTER__ + __COUNTER__;
+r = r + FOO + BAR + BAZ;
+#ifdef QUX
+r = 100;
+#endif
+return r;
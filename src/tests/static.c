@@ -0,0 +1,7 @@
+static int a = 3;
+static double b = 1.5;
+static unsigned c;
+extern int ext;
+c = a + b;
+ext = ext + c;
+return ext * 2 + c;
@@ -0,0 +1,22 @@
+#include <stddef.h>
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stddef.h>
+
+size_t n = 3;
+bool ok = true;
+int32_t a = INT32_MAX;
+uint32_t b = UINT32_MAX;
+int p = NULL;
+va_list ap = 0;
+
+n = n + (a == 2147483647);
+n = n + (b == 4294967295u) * 2;
+// unsigned なので 1 - 2 は大きな正の数
+n = n + (UINT32_C(1) - 2 > 0) * 4;
+n = n + (ok != false) * 8;
+#ifdef offsetof
+n = n + 16;
+#endif
+return n + p + ap + __bool_true_false_are_defined;
@@ -0,0 +1,12 @@
+unsigned a = 4294967295;
+int b = -1;
+int c = a / 2 == 2147483647;
+int d = b < 10u;
+int e = b < 10;
+int f = a > 1;
+int g = -7 / 2 == -3;
+int h = 1 < 1;
+// 0からはじまるのは8進数
+int i = 010 == 8;
+int j = 037777777777 == a;
+return c + d * 2 + e * 4 + f * 8 + g * 16 + h * 32 + i * 64 + j * 128;
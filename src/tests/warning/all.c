@@ -0,0 +1,8 @@
+// -Wall で出る警告 unused-variableが2つ、ほかは1つずつ
+int unused;
+int a;
+int b = a + 1;
+static int s = 2;
+b = (b < 1) < 2;
+b = b < 1 < 2;
+b;
@@ -1,8 +1,16 @@
+//! 字句解析
+
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::diagnostic::Diagnostic;
+
 #[derive(Debug)]
 enum CharType {
 	Whitespace,
-	Num(char),
-	Alphabetic(char),
+	Num,
+	Alphabetic,
 	NonAlphabetic(char),
 }
 
@@ -13,23 +21,32 @@ impl CharType {
 		}
 
 		if c.is_ascii_digit() {
-			return CharType::Num(c);
+			return CharType::Num;
 		}
 
-		if c.is_ascii_alphabetic() {
-			return CharType::Alphabetic(c);
+		// _も識別子につかえる
+		if c.is_ascii_alphabetic() || c == '_' {
+			return CharType::Alphabetic;
 		}
 
 		CharType::NonAlphabetic(c)
 	}
 }
 
+use crate::types::Type;
+
+/// トークンの種類
+/// 数値と識別子は中身も持つ
 #[derive(PartialEq, Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum TokenKind {
-	Num(i32),          // 整数
+	Num(i64, Type),    // 整数 値と型
+	FNum(f64, Type),   // 浮動小数点数 値と型
 	Ident(String),     // 識別子
+	Str(String),       // 文字列 "..." 今のところ#includeでしか使わない
+	Invalid(String),   // トークナイズできなかった文字 エラーメッセージ
 	Plus,              // +
-	Minus,             // +
+	Minus,             // -
 	Mul,               // *
 	Div,               // /
 	LeftParen,         // (
@@ -38,11 +55,35 @@ pub enum TokenKind {
 	RightAngleBracket, // >
 	Assign,            // =
 	SemiColon,         // ;
+	Dot,               // .
+	Mod,               // %
+	Amp,               // &
+	Pipe,              // |
+	Caret,             // ^
+	Not,               // !
+	Tilde,             // ~
+	Question,          // ?
+	Colon,             // :
+	Shl,               // <<
+	Shr,               // >>
+	AndAnd,            // &&
+	OrOr,              // ||
+	Comma,             // ,
+	Ellipsis,          // ...
+	Hash,              // #
+	HashHash,          // ##
 	EQ,                // ==
 	NEQ,               // !=
 	LE,                // <=
 	RE,                // >=
 	Return,            // return
+	Int,               // int
+	Signed,            // signed
+	Unsigned,          // unsigned
+	Float,             // float
+	Double,            // double
+	Static,            // static
+	Extern,            // extern
 	EOF,               // トークンの終端
 }
 
@@ -51,12 +92,50 @@ struct Symbol {
 	kind: TokenKind,
 }
 
-const RESERVED_WORDS: [Symbol; 1] = [Symbol {
-	name: "return",
-	kind: TokenKind::Return,
-}];
+const RESERVED_WORDS: [Symbol; 8] = [
+	Symbol {
+		name: "return",
+		kind: TokenKind::Return,
+	},
+	Symbol {
+		name: "int",
+		kind: TokenKind::Int,
+	},
+	Symbol {
+		name: "signed",
+		kind: TokenKind::Signed,
+	},
+	Symbol {
+		name: "unsigned",
+		kind: TokenKind::Unsigned,
+	},
+	Symbol {
+		name: "float",
+		kind: TokenKind::Float,
+	},
+	Symbol {
+		name: "double",
+		kind: TokenKind::Double,
+	},
+	Symbol {
+		name: "static",
+		kind: TokenKind::Static,
+	},
+	Symbol {
+		name: "extern",
+		kind: TokenKind::Extern,
+	},
+];
 
-const SYMBOL_LIST: [Symbol; 4] = [
+const SYMBOL_LIST: [Symbol; 10] = [
+	Symbol {
+		name: "...",
+		kind: TokenKind::Ellipsis,
+	},
+	Symbol {
+		name: "##",
+		kind: TokenKind::HashHash,
+	},
 	Symbol {
 		name: "==",
 		kind: TokenKind::EQ,
@@ -73,6 +152,22 @@ const SYMBOL_LIST: [Symbol; 4] = [
 		name: ">=",
 		kind: TokenKind::RE,
 	},
+	Symbol {
+		name: "<<",
+		kind: TokenKind::Shl,
+	},
+	Symbol {
+		name: ">>",
+		kind: TokenKind::Shr,
+	},
+	Symbol {
+		name: "&&",
+		kind: TokenKind::AndAnd,
+	},
+	Symbol {
+		name: "||",
+		kind: TokenKind::OrOr,
+	},
 ];
 
 impl TokenKind {
@@ -88,41 +183,232 @@ impl TokenKind {
 			'>' => Some(TokenKind::RightAngleBracket),
 			'=' => Some(TokenKind::Assign),
 			';' => Some(TokenKind::SemiColon),
+			'.' => Some(TokenKind::Dot),
+			'%' => Some(TokenKind::Mod),
+			'&' => Some(TokenKind::Amp),
+			'|' => Some(TokenKind::Pipe),
+			'^' => Some(TokenKind::Caret),
+			'!' => Some(TokenKind::Not),
+			'~' => Some(TokenKind::Tilde),
+			'?' => Some(TokenKind::Question),
+			':' => Some(TokenKind::Colon),
+			',' => Some(TokenKind::Comma),
+			'#' => Some(TokenKind::Hash),
 			_ => None,
 		}
 	}
 }
 
-#[derive(Debug)]
+/// ソースコード上の位置
+/// #includeしたファイルのトークンも元のファイル名と行を覚えておく
+#[derive(Clone, PartialEq)]
+pub struct Loc {
+	/// ファイル名
+	pub file: Rc<str>,
+	/// 行 1はじまり
+	pub line: usize,
+	/// 列 1はじまり 文字単位
+	pub col: usize,
+	/// ファイルの先頭からのバイト数 0はじまり
+	pub offset: usize,
+}
+
+impl fmt::Display for Loc {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}:{}", self.file, self.line, self.col)
+	}
+}
+
+// {:#?}で表示したときに長くならないようにDisplayとおなじにしとく
+impl fmt::Debug for Loc {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self)
+	}
+}
+
+/// トークン
+#[derive(Debug, Clone)]
 pub struct Token {
 	pub kind: TokenKind,
+	/// 先頭の文字の位置
+	pub loc: Loc,
+	/// ソースコード上の綴り
+	pub text: String,
+	/// 行頭のトークンかどうか プリプロセッサのディレクティブの判定につかう
+	pub at_bol: bool,
+	/// 前に空白があるかどうか
+	pub has_space: bool,
+	/// このトークンを展開して作られたマクロの名前 再帰的に展開しないようにする
+	pub hideset: Vec<String>,
+	/// マクロ展開で作られたトークンなら、展開したところ
+	pub origin: Option<Loc>,
 }
 
 impl Token {
-	pub fn new(kind: TokenKind) -> Self {
-		Token { kind }
+	/// loc.fileのソースの中でこのトークンが占めるバイトの範囲
+	/// マクロ展開や##で作られたトークンは、もとになったトークンの位置からtextの長さ分になる
+	pub fn span(&self) -> Range<usize> {
+		self.loc.offset..self.loc.offset + self.text.len()
 	}
 
-	fn new_eof() -> Self {
-		Token {
-			kind: TokenKind::EOF,
+	/// このトークンの位置のエラー
+	/// マクロを展開したトークンなら展開したところも補足につける
+	pub fn error(&self, msg: impl Into<String>) -> Diagnostic {
+		let mut diagnostic = Diagnostic::error(&self.loc, msg).with_len(self.text.len());
+		if let Some(ref origin) = self.origin {
+			diagnostic.notes.push(Diagnostic::note(origin, "ここで展開されたマクロです"));
 		}
-	}
-
-	pub fn bad_token(&self, msg: &str) -> ! {
-		panic!("{}", msg);
+		diagnostic
 	}
 }
 
+/// ソースの文字列をトークン列にする
+/// プリプロセスはしないので、#や マクロはそのまま残る
+///
+/// ```
+/// use rust9cc::token::{TokenKind, Tokenizer};
+///
+/// let tokens = Tokenizer::new("a.c", "return 1;").generate();
+/// assert_eq!(tokens[0].kind, TokenKind::Return);
+/// assert_eq!(tokens[1].span(), 7..8);
+/// ```
 pub struct Tokenizer {
+	file: Rc<str>,
 	chars: Vec<char>,
+	// chars[i]がソースの何バイト目か 末尾にソースの長さも入れておく
+	offsets: Vec<usize>,
 	pos: usize,
+	// 今の行と、その行の先頭のpos
+	line: usize,
+	line_start: usize,
+	// 次のトークンが行頭か、前に空白があるか
+	at_bol: bool,
+	has_space: bool,
 }
 
 impl Tokenizer {
-	pub fn new(s: &String) -> Self {
+	/// fileはエラーやLocに出すファイル名 sはソース
+	pub fn new(file: &str, s: &str) -> Self {
 		let chars = s.chars().collect();
-		Tokenizer { chars, pos: 0 }
+		let offsets = s.char_indices().map(|(i, _)| i).chain(std::iter::once(s.len())).collect();
+		Tokenizer {
+			file: Rc::from(file),
+			chars,
+			offsets,
+			pos: 0,
+			line: 1,
+			line_start: 0,
+			at_bol: true,
+			has_space: false,
+		}
+	}
+
+	// startから今のposまでをトークンにする
+	fn new_token(&mut self, kind: TokenKind, start: usize) -> Token {
+		let token = Token {
+			kind,
+			loc: Loc {
+				file: self.file.clone(),
+				line: self.line,
+				col: start - self.line_start + 1,
+				offset: self.offsets[start],
+			},
+			text: self.chars[start..self.pos].iter().collect(),
+			at_bol: self.at_bol,
+			has_space: self.has_space,
+			hideset: Vec::new(),
+			origin: None,
+		};
+		self.at_bol = false;
+		self.has_space = false;
+		token
+	}
+
+	// 改行をまたいだら行番号をすすめる
+	fn newline(&mut self) {
+		self.line += 1;
+		self.line_start = self.pos;
+		self.at_bol = true;
+	}
+
+	// 空白とコメントを読み飛ばす
+	// 読み飛ばしたらtrue
+	fn skip_space(&mut self) -> bool {
+		let c = self.chars[self.pos];
+		let next = self.chars.get(self.pos + 1);
+		if c == '\n' {
+			self.pos += 1;
+			self.newline();
+		} else if c.is_ascii_whitespace() {
+			self.pos += 1;
+		} else if c == '\\' && next == Some(&'\n') {
+			// 行末の\は次の行とつなげる
+			self.pos += 2;
+			self.line += 1;
+			self.line_start = self.pos;
+		} else if c == '/' && next == Some(&'/') {
+			while self.pos < self.chars.len() && self.chars[self.pos] != '\n' {
+				self.pos += 1;
+			}
+		} else if c == '/' && next == Some(&'*') && self.has_comment_end() {
+			self.pos += 2;
+			loop {
+				match self.chars.get(self.pos) {
+					Some('*') if self.chars.get(self.pos + 1) == Some(&'/') => {
+						self.pos += 2;
+						break;
+					}
+					Some('\n') => {
+						self.pos += 1;
+						// コメントの途中の改行は行頭扱いにしない
+						let at_bol = self.at_bol;
+						self.newline();
+						self.at_bol = at_bol;
+					}
+					Some(_) => self.pos += 1,
+					None => unreachable!(),
+				}
+			}
+		} else {
+			return false;
+		}
+		self.has_space = true;
+		true
+	}
+
+	// /* に対応する */ があるかどうか
+	// なければ閉じられていないコメントとしてエラーのトークンにする
+	fn has_comment_end(&self) -> bool {
+		self.chars[self.pos + 2..].windows(2).any(|w| w == ['*', '/'])
+	}
+
+	// 文字列リテラルを読む
+	// 返り値は(エスケープを処理した中身, 読んだ文字数) 閉じられていなければNone
+	fn get_string(&self) -> Option<(String, usize)> {
+		let mut pos = self.pos + 1;
+		let mut s = String::new();
+		loop {
+			match self.chars.get(pos) {
+				Some('"') => break,
+				Some('\\') => {
+					let c = match self.chars.get(pos + 1) {
+						Some('n') => '\n',
+						Some('t') => '\t',
+						Some('0') => '\0',
+						Some(c) => *c,
+						None => return None,
+					};
+					s.push(c);
+					pos += 2;
+				}
+				Some('\n') | None => return None,
+				Some(c) => {
+					s.push(*c);
+					pos += 1;
+				}
+			}
+		}
+		Some((s, pos + 1 - self.pos))
 	}
 
 	fn get_by_pos(&self, pos: usize) -> Option<CharType> {
@@ -131,26 +417,162 @@ impl Tokenizer {
 
 	// 予約語/変数名を取得する
 	fn get_keyword(&self) -> String {
+		let mut pos = self.pos + 1;
+		// 末尾に到達した場合もそこまでをキーワードとする
+		while let Some(CharType::Alphabetic) | Some(CharType::Num) = self.get_by_pos(pos) {
+			pos += 1;
+		}
+		let keyword = &self.chars[self.pos..pos];
+		keyword.iter().collect::<String>()
+	}
+
+	// 数値リテラルを読む
+	// 10進数と0xからはじまる16進数、0からはじまる8進数、u/Uのサフィックスに対応
+	// 小数点や指数部があれば浮動小数点数として読む
+	// 返り値は(トークン, 読んだ文字数)
+	fn get_number(&self) -> (TokenKind, usize) {
 		let mut pos = self.pos;
-		loop {
+		let is_hex = self.chars.get(pos) == Some(&'0') && matches!(self.chars.get(pos + 1), Some('x') | Some('X'));
+		let radix = if is_hex {
+			pos += 2;
+			16
+		} else {
+			10
+		};
+
+		let start = pos;
+		while let Some(c) = self.chars.get(pos) {
+			if !c.is_digit(radix) {
+				break;
+			}
+			pos += 1;
+		}
+
+		// 小数点か指数部がくれば浮動小数点数
+		match self.chars.get(pos) {
+			Some('.') => return self.get_float(is_hex),
+			Some('e') | Some('E') if !is_hex => return self.get_float(is_hex),
+			Some('p') | Some('P') if is_hex => return self.get_float(is_hex),
+			_ => {}
+		}
+
+		// 012.5 や 09e1 は10進数の浮動小数点数なので、8進数かどうかは小数点を見てから決める
+		let digits = self.chars[start..pos].iter().collect::<String>();
+		let radix = if !is_hex && digits.len() > 1 && digits.starts_with('0') { 8 } else { radix };
+		let val = match u64::from_str_radix(&digits, radix) {
+			Ok(v) => v,
+			Err(_) => return (TokenKind::Invalid(format!("数値として読めない:{}", digits)), pos - self.pos),
+		};
+
+		let mut is_unsigned = false;
+		if let Some('u') | Some('U') = self.chars.get(pos) {
+			is_unsigned = true;
 			pos += 1;
-			if let Some(c) = self.get_by_pos(pos) {
-				match c {
-					CharType::Alphabetic(_) => continue,
-					CharType::Num(_) => continue,
-					_ => {
-						let keyword = &self.chars[self.pos..pos];
-						let keyword = keyword.iter().collect::<String>();
-						return keyword;
+		}
+
+		// 型の決定
+		// 本来intに収まらない10進数はlongになるけどlongがないので、
+		// 16進数と同じくunsigned intに収まるならunsigned intにする(C89と同じ)
+		let ty = if !is_unsigned && val <= i32::MAX as u64 {
+			Type::Int
+		} else if val <= u32::MAX as u64 {
+			Type::UInt
+		} else {
+			return (TokenKind::Invalid(format!("整数リテラルが大きすぎる:{}", digits)), pos - self.pos);
+		};
+
+		(TokenKind::Num(val as i64, ty), pos - self.pos)
+	}
+
+	// 浮動小数点数リテラルを読む
+	// 1.5 .5 1e10 1.5e-3 0x1.8p3 のような形式。f/Fのサフィックスでfloat、なければdouble
+	// 読めなかったときはInvalidを返す
+	fn get_float(&self, is_hex: bool) -> (TokenKind, usize) {
+		let mut pos = self.pos;
+		let val = if is_hex {
+			// 16進数はparseできないので自分で計算する
+			// 仮数部を整数として読んで、小数点以下の桁数ぶん2の指数を減らす
+			pos += 2;
+			let mut mant = 0.0;
+			let mut exp = 0;
+			let mut seen_dot = false;
+			while let Some(c) = self.chars.get(pos) {
+				if *c == '.' && !seen_dot {
+					seen_dot = true;
+				} else if let Some(d) = c.to_digit(16) {
+					mant = mant * 16.0 + d as f64;
+					if seen_dot {
+						exp -= 4;
 					}
+				} else {
+					break;
 				}
-			} else {
-				// TODO エラー箇所を表示できるようにする
-				panic!("get_keyword error");
+				pos += 1;
 			}
+			// 16進数の場合、指数部(p)は必須
+			if !matches!(self.chars.get(pos), Some('p') | Some('P')) {
+				let msg = "16進数の浮動小数点数には指数部が必要".to_string();
+				return (TokenKind::Invalid(msg), pos - self.pos);
+			}
+			pos += 1;
+			let (e, len) = match self.get_exponent(pos) {
+				Some(v) => v,
+				None => return (TokenKind::Invalid("指数部が読めない".to_string()), pos - self.pos),
+			};
+			pos += len;
+			mant * 2f64.powi(exp + e)
+		} else {
+			while let Some(c) = self.chars.get(pos) {
+				if !c.is_ascii_digit() && *c != '.' {
+					break;
+				}
+				pos += 1;
+			}
+			let mut text = self.chars[self.pos..pos].iter().collect::<String>();
+			if let Some('e') | Some('E') = self.chars.get(pos) {
+				let (e, len) = match self.get_exponent(pos + 1) {
+					Some(v) => v,
+					None => return (TokenKind::Invalid("指数部が読めない".to_string()), pos + 1 - self.pos),
+				};
+				pos += len + 1;
+				text = format!("{}e{}", text, e);
+			}
+			match text.parse::<f64>() {
+				Ok(v) => v,
+				Err(_) => return (TokenKind::Invalid(format!("数値として読めない:{}", text)), pos - self.pos),
+			}
+		};
+
+		let ty = if let Some('f') | Some('F') = self.chars.get(pos) {
+			pos += 1;
+			Type::Float
+		} else {
+			Type::Double
+		};
+		let val = if ty == Type::Float { val as f32 as f64 } else { val };
+
+		(TokenKind::FNum(val, ty), pos - self.pos)
+	}
+
+	// 指数部の符号付き10進数を読む
+	// 返り値は(値, 読んだ文字数)
+	fn get_exponent(&self, start: usize) -> Option<(i32, usize)> {
+		let mut pos = start;
+		if let Some('+') | Some('-') = self.chars.get(pos) {
+			pos += 1;
+		}
+		while let Some(c) = self.chars.get(pos) {
+			if !c.is_ascii_digit() {
+				break;
+			}
+			pos += 1;
 		}
+		let text = self.chars[start..pos].iter().collect::<String>();
+		text.parse::<i32>().ok().map(|v| (v, pos - start))
 	}
 
+	/// トークン列を返す 最後は必ずEOF
+	/// トークナイズできなかったところはエラーにせずにInvalidのトークンにする
 	pub fn generate(&mut self) -> Vec<Token> {
 		let mut tokens = Vec::new();
 
@@ -158,30 +580,64 @@ impl Tokenizer {
 		// イテレータを移動させなきゃいけない処理が頻発するので
 		// parserとおなじposによるindexアクセスがいいんだね
 		'outer: while let Some(c) = self.get_by_pos(self.pos) {
+			if self.skip_space() {
+				continue;
+			}
+
+			let start = self.pos;
 			match c {
-				CharType::Whitespace => self.pos += 1,
-				CharType::Alphabetic(_) => {
+				CharType::Whitespace => unreachable!(),
+				CharType::Alphabetic => {
 					let keyword = self.get_keyword();
-					let len = keyword.len();
+					self.pos += keyword.len();
 					// 予約語の判定
 					if let Some(reserved_word) = RESERVED_WORDS.iter().find(|symbol| symbol.name == keyword) {
-						tokens.push(Token::new(reserved_word.kind.clone()));
-						self.pos += reserved_word.name.len();
+						let token = self.new_token(reserved_word.kind.clone(), start);
+						tokens.push(token);
 						continue 'outer;
 					}
 
 					// 予約後じゃなかったら変数
-					let token = Token::new(TokenKind::Ident(keyword));
+					let token = self.new_token(TokenKind::Ident(keyword), start);
+					tokens.push(token);
+				}
+				CharType::Num => {
+					let (kind, len) = self.get_number();
+					self.pos += len;
+					let token = self.new_token(kind, start);
 					tokens.push(token);
+				}
+				// skip_spaceで読み飛ばせなかった /* は閉じられていないコメント
+				// エラーは /* のところにして、残りは全部読み飛ばす
+				CharType::NonAlphabetic('/') if self.chars.get(self.pos + 1) == Some(&'*') => {
+					self.pos += 2;
+					let token = self.new_token(TokenKind::Invalid("コメントが閉じられていない".to_string()), start);
+					tokens.push(token);
+					self.pos = self.chars.len();
+				}
+				// .5 のような数値
+				CharType::NonAlphabetic('.') if matches!(self.get_by_pos(self.pos + 1), Some(CharType::Num)) => {
+					let (kind, len) = self.get_float(false);
 					self.pos += len;
+					let token = self.new_token(kind, start);
+					tokens.push(token);
 				}
-				CharType::Num(c) => {
-					// これでいいのかふあん
-					// char to i32
-					let val = c.to_digit(10).unwrap() as i32;
-					let token = Token::new(TokenKind::Num(val));
+				CharType::NonAlphabetic('"') => {
+					let kind = match self.get_string() {
+						Some((s, len)) => {
+							self.pos += len;
+							TokenKind::Str(s)
+						}
+						// 閉じられていない文字列は行末までをエラーのトークンにする
+						None => {
+							while self.pos < self.chars.len() && self.chars[self.pos] != '\n' {
+								self.pos += 1;
+							}
+							TokenKind::Invalid("文字列が閉じられていない".to_string())
+						}
+					};
+					let token = self.new_token(kind, start);
 					tokens.push(token);
-					self.pos += 1
 				}
 				CharType::NonAlphabetic(c) => {
 					// multi char
@@ -196,8 +652,9 @@ impl Tokenizer {
 						let key = key.iter().collect::<String>();
 
 						if symbol.name == key {
-							tokens.push(Token::new(symbol.kind.clone()));
 							self.pos += symbol.name.len();
+							let token = self.new_token(symbol.kind.clone(), start);
+							tokens.push(token);
 							// loopがネストしてるので
 							continue 'outer;
 						}
@@ -205,18 +662,22 @@ impl Tokenizer {
 
 					// single char
 					if let Some(token_kind) = TokenKind::new_single_letter(c) {
-						let token = Token::new(token_kind);
+						self.pos += 1;
+						let token = self.new_token(token_kind, start);
 						tokens.push(token);
-						self.pos += 1
 					} else {
 						// 存在しない記号
-						panic!("知らない記号:{}", c);
+						// #if 0 の中などでは問題ないので、ここではエラーにせずプリプロセッサに任せる
+						self.pos += 1;
+						let token = self.new_token(TokenKind::Invalid(format!("知らない記号:{}", c)), start);
+						tokens.push(token);
 					}
 				}
 			}
 		}
 
-		tokens.push(Token::new_eof());
+		let token = self.new_token(TokenKind::EOF, self.pos);
+		tokens.push(token);
 		tokens
 	}
 }
@@ -0,0 +1,59 @@
+//! 型
+
+use std::fmt;
+
+/// 型
+/// char/shortがないので整数拡張(integer promotion)は実質なにもしない
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+	Int,    // int / signed / signed int
+	UInt,   // unsigned / unsigned int
+	Float,  // float
+	Double, // double
+}
+
+impl Type {
+	pub fn is_unsigned(&self) -> bool {
+		matches!(self, Type::UInt)
+	}
+
+	// 浮動小数点数かどうか
+	// codegenではこれでSSEを使うかどうかを切り替える
+	pub fn is_flonum(&self) -> bool {
+		matches!(self, Type::Float | Type::Double)
+	}
+
+	// 整数拡張
+	// intより小さい型がないのでそのまま返す
+	pub fn promote(self) -> Type {
+		self
+	}
+
+	// 通常の算術型変換(usual arithmetic conversions)
+	// double > float > unsigned > int の順に強いほうにそろえる
+	pub fn common(lhs: Type, rhs: Type) -> Type {
+		let lhs = lhs.promote();
+		let rhs = rhs.promote();
+		if lhs == Type::Double || rhs == Type::Double {
+			Type::Double
+		} else if lhs == Type::Float || rhs == Type::Float {
+			Type::Float
+		} else if lhs.is_unsigned() || rhs.is_unsigned() {
+			Type::UInt
+		} else {
+			Type::Int
+		}
+	}
+}
+
+// Cでの型名
+impl fmt::Display for Type {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Type::Int => write!(f, "int"),
+			Type::UInt => write!(f, "unsigned int"),
+			Type::Float => write!(f, "float"),
+			Type::Double => write!(f, "double"),
+		}
+	}
+}
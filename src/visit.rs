@@ -0,0 +1,182 @@
+//! 構文木をたどる
+//!
+//! [`Visitor`] は読むだけ、[`VisitorMut`] は書き換えながらたどる
+//! どちらもノードの種類ごとのメソッドがあって、デフォルトでは子をたどるだけなので
+//! 必要なノードのメソッドだけ上書きすればいい
+//! 上書きしたメソッドで子もたどりたいときは [`walk_children`] / [`walk_children_mut`] を呼ぶ
+//!
+//! ```
+//! use rust9cc::parse::Node;
+//! use rust9cc::visit::Visitor;
+//! use rust9cc::{parse_str, Config};
+//!
+//! // 変数を参照している回数を数える
+//! struct CountVars(usize);
+//!
+//! impl Visitor for CountVars {
+//!     fn visit_lvar(&mut self, _node: &Node) {
+//!         self.0 += 1;
+//!     }
+//! }
+//!
+//! let program = parse_str("int a = 1; a = a + 2; return a;", &Config::default()).unwrap();
+//! let mut count = CountVars(0);
+//! count.visit_program(&program);
+//! assert_eq!(count.0, 4);
+//! ```
+
+use crate::parse::{Node, NodeKind, Program};
+
+pub trait Visitor {
+	fn visit_program(&mut self, program: &Program) {
+		walk_program(self, program)
+	}
+
+	// ノードの種類ごとのメソッドにふりわける
+	fn visit_node(&mut self, node: &Node) {
+		walk_node(self, node)
+	}
+
+	fn visit_num(&mut self, _node: &Node) {}
+
+	fn visit_fnum(&mut self, _node: &Node) {}
+
+	fn visit_binop(&mut self, node: &Node) {
+		walk_children(self, node)
+	}
+
+	fn visit_assign(&mut self, node: &Node) {
+		walk_children(self, node)
+	}
+
+	fn visit_lvar(&mut self, _node: &Node) {}
+
+	fn visit_gvar(&mut self, _node: &Node) {}
+
+	fn visit_return(&mut self, node: &Node) {
+		walk_children(self, node)
+	}
+
+	fn visit_cast(&mut self, node: &Node) {
+		walk_children(self, node)
+	}
+
+	// 変数(LVar/GVar)、初期化式の順にたどる
+	fn visit_decl(&mut self, node: &Node) {
+		walk_children(self, node)
+	}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+	for node in &program.nodes {
+		visitor.visit_node(node);
+	}
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+	match node.kind {
+		NodeKind::Num(_) => visitor.visit_num(node),
+		NodeKind::FNum(_) => visitor.visit_fnum(node),
+		NodeKind::BinOp(..) => visitor.visit_binop(node),
+		NodeKind::Assign(..) => visitor.visit_assign(node),
+		NodeKind::LVar(..) => visitor.visit_lvar(node),
+		NodeKind::GVar(..) => visitor.visit_gvar(node),
+		NodeKind::Return(_) => visitor.visit_return(node),
+		NodeKind::Cast(_) => visitor.visit_cast(node),
+		NodeKind::Decl(..) => visitor.visit_decl(node),
+	}
+}
+
+// 子のノードを左から順にたどる
+pub fn walk_children<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+	match node.kind {
+		NodeKind::Num(_) | NodeKind::FNum(_) | NodeKind::LVar(..) | NodeKind::GVar(..) => {}
+		NodeKind::BinOp(_, ref lhs, ref rhs) | NodeKind::Assign(ref lhs, ref rhs) => {
+			visitor.visit_node(lhs);
+			visitor.visit_node(rhs);
+		}
+		NodeKind::Return(ref expr) | NodeKind::Cast(ref expr) => visitor.visit_node(expr),
+		NodeKind::Decl(ref var, ref init) => {
+			visitor.visit_node(var);
+			if let Some(init) = init {
+				visitor.visit_node(init);
+			}
+		}
+	}
+}
+
+// Visitorの書き換えられる版
+pub trait VisitorMut {
+	fn visit_program_mut(&mut self, program: &mut Program) {
+		walk_program_mut(self, program)
+	}
+
+	fn visit_node_mut(&mut self, node: &mut Node) {
+		walk_node_mut(self, node)
+	}
+
+	fn visit_num_mut(&mut self, _node: &mut Node) {}
+
+	fn visit_fnum_mut(&mut self, _node: &mut Node) {}
+
+	fn visit_binop_mut(&mut self, node: &mut Node) {
+		walk_children_mut(self, node)
+	}
+
+	fn visit_assign_mut(&mut self, node: &mut Node) {
+		walk_children_mut(self, node)
+	}
+
+	fn visit_lvar_mut(&mut self, _node: &mut Node) {}
+
+	fn visit_gvar_mut(&mut self, _node: &mut Node) {}
+
+	fn visit_return_mut(&mut self, node: &mut Node) {
+		walk_children_mut(self, node)
+	}
+
+	fn visit_cast_mut(&mut self, node: &mut Node) {
+		walk_children_mut(self, node)
+	}
+
+	fn visit_decl_mut(&mut self, node: &mut Node) {
+		walk_children_mut(self, node)
+	}
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+	for node in &mut program.nodes {
+		visitor.visit_node_mut(node);
+	}
+}
+
+pub fn walk_node_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut Node) {
+	match node.kind {
+		NodeKind::Num(_) => visitor.visit_num_mut(node),
+		NodeKind::FNum(_) => visitor.visit_fnum_mut(node),
+		NodeKind::BinOp(..) => visitor.visit_binop_mut(node),
+		NodeKind::Assign(..) => visitor.visit_assign_mut(node),
+		NodeKind::LVar(..) => visitor.visit_lvar_mut(node),
+		NodeKind::GVar(..) => visitor.visit_gvar_mut(node),
+		NodeKind::Return(_) => visitor.visit_return_mut(node),
+		NodeKind::Cast(_) => visitor.visit_cast_mut(node),
+		NodeKind::Decl(..) => visitor.visit_decl_mut(node),
+	}
+}
+
+pub fn walk_children_mut<V: VisitorMut + ?Sized>(visitor: &mut V, node: &mut Node) {
+	match node.kind {
+		NodeKind::Num(_) | NodeKind::FNum(_) | NodeKind::LVar(..) | NodeKind::GVar(..) => {}
+		NodeKind::BinOp(_, ref mut lhs, ref mut rhs) | NodeKind::Assign(ref mut lhs, ref mut rhs) => {
+			visitor.visit_node_mut(lhs);
+			visitor.visit_node_mut(rhs);
+		}
+		NodeKind::Return(ref mut expr) | NodeKind::Cast(ref mut expr) => visitor.visit_node_mut(expr),
+		NodeKind::Decl(ref mut var, ref mut init) => {
+			visitor.visit_node_mut(var);
+			if let Some(init) = init {
+				visitor.visit_node_mut(init);
+			}
+		}
+	}
+}
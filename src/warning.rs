@@ -0,0 +1,225 @@
+// 警告
+// 意味解析でエラーがなかった構文木をたどって、コンパイルはできるけどあやしいところを見つける
+// ここではとりあえず全部の警告を集めて、-W/-Wno- で有効になっているものだけ返す
+
+use crate::config::Config;
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::parse::{Node, NodeKind, Program};
+use crate::token::{Loc, TokenKind};
+use crate::visit::{self, Visitor};
+
+/// -W<name> で有効に、-Wno-<name> で無効にできる警告
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Warning {
+	/// 宣言したけど一度も使っていない変数
+	UnusedVariable,
+	/// 初期化も代入もしていない変数を読んでいる
+	Uninitialized,
+	/// mainの最後にreturnがない
+	ReturnType,
+	/// a < b < c のような括弧のない比較の比較
+	Parentheses,
+}
+
+// -Wall で有効になる警告 いまある警告は全部
+pub const ALL: [Warning; 4] = [Warning::UnusedVariable, Warning::Uninitialized, Warning::ReturnType, Warning::Parentheses];
+
+// -Wextra gccとおなじく -Wuninitialized を有効にする
+pub const EXTRA: [Warning; 1] = [Warning::Uninitialized];
+
+impl Warning {
+	// -W のあとにつける名前
+	pub fn name(self) -> &'static str {
+		match self {
+			Warning::UnusedVariable => "unused-variable",
+			Warning::Uninitialized => "uninitialized",
+			Warning::ReturnType => "return-type",
+			Warning::Parentheses => "parentheses",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Option<Warning> {
+		ALL.iter().copied().find(|w| w.name() == name)
+	}
+
+	/// 警告を有効にするオプション -Wunused-variable など
+	pub fn flag(self) -> String {
+		format!("-W{}", self.name())
+	}
+}
+
+// 変数を区別するもの
+// externの変数はここで定義しないので警告の対象にしない
+#[derive(PartialEq)]
+enum VarId {
+	// RBPからのオフセット
+	Local(usize),
+	// static変数のラベル
+	Static(String),
+}
+
+// 宣言した変数
+struct Declared {
+	id: VarId,
+	name: String,
+	loc: Loc,
+	used: bool,
+}
+
+struct Checker<'a> {
+	program: &'a Program,
+	vars: Vec<Declared>,
+	// 初期化か代入をしたローカル変数
+	// 制御構文がないので、上から順にたどればわかる
+	initialized: Vec<usize>,
+	warnings: Vec<(Warning, Diagnostic)>,
+}
+
+impl Checker<'_> {
+	fn var_id(&self, node: &Node) -> Option<VarId> {
+		match node.kind {
+			NodeKind::LVar(_, offset) => Some(VarId::Local(offset)),
+			NodeKind::GVar(_, ref label) if self.program.gvars.iter().any(|gvar| gvar.label == *label) => {
+				Some(VarId::Static(label.clone()))
+			}
+			_ => None,
+		}
+	}
+
+	// lenは変数名のようにlocから指すバイト数
+	fn warn(&mut self, warning: Warning, loc: &Loc, len: usize, message: impl Into<String>) {
+		self.warnings.push((warning, Diagnostic::warning(loc, warning, message).with_len(len)));
+	}
+
+	// 変数を参照した
+	fn use_var(&mut self, node: &Node) {
+		let id = self.var_id(node);
+		if let Some(var) = self.vars.iter_mut().rev().find(|var| Some(&var.id) == id.as_ref()) {
+			var.used = true;
+		}
+	}
+
+	fn initialize(&mut self, node: &Node) {
+		if let NodeKind::LVar(_, offset) = node.kind {
+			self.initialized.push(offset);
+		}
+	}
+}
+
+impl Visitor for Checker<'_> {
+	fn visit_decl(&mut self, node: &Node) {
+		if let NodeKind::Decl(ref var, ref init) = node.kind {
+			if let Some(id) = self.var_id(var) {
+				self.vars.push(Declared {
+					id,
+					name: var_name(var).to_string(),
+					loc: var.loc.clone(),
+					used: false,
+				});
+			}
+			// int a = a; は初期化する前に読んでいる
+			if let Some(init) = init {
+				self.visit_node(init);
+				self.initialize(var);
+			}
+		}
+	}
+
+	fn visit_lvar(&mut self, node: &Node) {
+		self.use_var(node);
+		if let NodeKind::LVar(ref name, offset) = node.kind {
+			if !self.initialized.contains(&offset) {
+				self.warn(Warning::Uninitialized, &node.loc, name.len(), format!("{} は初期化しないまま使われています。", name));
+				// 同じ変数で何度も出さない
+				self.initialized.push(offset);
+			}
+		}
+	}
+
+	fn visit_gvar(&mut self, node: &Node) {
+		self.use_var(node);
+	}
+
+	// 左辺は読まないので、右辺をたどってから左辺に代入したことにする
+	fn visit_assign(&mut self, node: &Node) {
+		if let NodeKind::Assign(ref lhs, ref rhs) = node.kind {
+			self.visit_node(rhs);
+			self.use_var(lhs);
+			self.initialize(lhs);
+		}
+	}
+
+	fn visit_binop(&mut self, node: &Node) {
+		if let NodeKind::BinOp(ref kind, ref lhs, ref rhs) = node.kind {
+			if is_comparison(kind) && [lhs, rhs].iter().any(|side| !side.parens && is_comparison_node(side)) {
+				self.warn(
+					Warning::Parentheses,
+					&node.loc,
+					0,
+					"比較の結果をさらに比較しています。a < b < c は数学の意味にはならないので、そのつもりなら括弧でかこんでください。",
+				);
+			}
+		}
+		visit::walk_children(self, node);
+	}
+}
+
+fn var_name(node: &Node) -> &str {
+	match node.kind {
+		NodeKind::LVar(ref name, _) | NodeKind::GVar(ref name, _) => name,
+		_ => unreachable!(),
+	}
+}
+
+fn is_comparison(kind: &TokenKind) -> bool {
+	matches!(kind, TokenKind::EQ | TokenKind::NEQ | TokenKind::LE | TokenKind::LeftAngleBracket)
+}
+
+fn is_comparison_node(node: &Node) -> bool {
+	matches!(node.kind, NodeKind::BinOp(ref kind, ..) if is_comparison(kind))
+}
+
+// configで有効になっている警告を返す
+// -Werror ならエラーにする
+pub fn check(program: &Program, config: &Config) -> Vec<Diagnostic> {
+	let mut checker = Checker {
+		program,
+		vars: Vec::new(),
+		initialized: Vec::new(),
+		warnings: Vec::new(),
+	};
+	checker.visit_program(program);
+
+	let unused: Vec<_> = checker.vars.iter().filter(|var| !var.used).map(|var| (var.loc.clone(), var.name.clone())).collect();
+	for (loc, name) in unused {
+		checker.warn(Warning::UnusedVariable, &loc, name.len(), format!("{} は使われていません。", name));
+	}
+
+	// returnがないとmainは最後の式の値を返してしまう
+	// 制御構文がないので、どこかにreturnがあれば最後まではこない
+	if !program.nodes.iter().any(|node| matches!(node.kind, NodeKind::Return(_))) {
+		let message = "mainの最後にreturnがありません。";
+		let diagnostic = match program.nodes.last() {
+			Some(node) => Diagnostic::warning(&node.loc, Warning::ReturnType, message),
+			// 空のファイル
+			None => Diagnostic {
+				severity: Severity::Warning,
+				warning: Some(Warning::ReturnType),
+				..Diagnostic::without_loc(message)
+			},
+		};
+		checker.warnings.push((Warning::ReturnType, diagnostic));
+	}
+
+	checker
+		.warnings
+		.into_iter()
+		.filter(|(warning, _)| config.warnings.contains(warning))
+		.map(|(_, mut diagnostic)| {
+			if config.werror {
+				diagnostic.severity = Severity::Error;
+			}
+			diagnostic
+		})
+		.collect()
+}
@@ -3,8 +3,8 @@ assert() {
   expected="$1"
   input="$2"
 
-  cargo run "$input"
-  cc -o tmp tmp.s
+  # inputにはオプションも書けるようにクォートしない
+  cargo run -- -o tmp $input
   ./tmp
   actual="$?"
 
@@ -16,8 +16,185 @@ assert() {
   fi
 }
 
+# static.c のextern変数の定義はライブラリにしてリンクする
+as -o target/extern.o src/tests/extern.s
+ar rcs target/libextern.a target/extern.o
+
 assert 7 src/tests/expr.c
 assert 1 src/tests/comp.c
 assert 2 src/tests/base.c
+assert 221 src/tests/unsigned.c
+assert 66 src/tests/float.c
+assert 32 "src/tests/static.c src/tests/extern.s"
+assert 32 "src/tests/static.c target/extern.o"
+assert 32 "-L target -lextern src/tests/static.c"
+assert 16 "-I src/tests/include src/tests/include.c"
+assert 60 src/tests/macro.c
+assert 15 src/tests/cond.c
+assert 18 "-D FOO=3 -DBAR -U BAZ -DBAZ=7 -D QUX -UQUX src/tests/predef.c"
+assert 35 src/tests/stdhdr.c
+
+# 出力がスナップショットとおなじかどうか
+assert_dump() {
+  expected="$1"
+  input="${@:2}"
+
+  cargo run -q -- $input > tmp.out
+  if diff -u "$expected" tmp.out; then
+    echo "$input => $expected"
+  else
+    echo "$input => $expected とちがいます"
+    exit 1
+  fi
+  rm -f tmp.out
+}
+
+assert_dump src/tests/dump/comp.tokens --dump-tokens src/tests/comp.c
+assert_dump src/tests/dump/comp.tokens.json --dump-tokens=json src/tests/comp.c
+assert_dump src/tests/dump/comp.ast --dump-ast src/tests/comp.c
+assert_dump src/tests/dump/comp.ast.json --dump-ast=json src/tests/comp.c
+assert_dump src/tests/dump/twice.i -E src/tests/dump/twice.c
+
+# コンパイルに失敗して、位置つきのエラーがexpected個出るかどうか
+assert_error() {
+  expected="$1"
+  input="${@:2}"
+
+  if cargo run -q -- -S -o - $input > /dev/null 2> tmp.out; then
+    echo "$input => エラーになりませんでした"
+    exit 1
+  fi
+  actual=$(grep -oE '[0-9]+:[0-9]+: error: ' tmp.out | wc -l)
+  if [ "$actual" = "$expected" ]; then
+    echo "$input => $actual errors"
+  else
+    cat tmp.out
+    echo "$input => $expected errors expected, but got $actual"
+    exit 1
+  fi
+  rm -f tmp.out
+}
+
+assert_error 3 src/tests/error/syntax.c
+assert_error 2 -ferror-limit=2 src/tests/error/syntax.c
+assert_error 6 src/tests/error/sema.c
+
+# コンパイルはできて、警告がexpected個出るかどうか
+assert_warning() {
+  expected="$1"
+  input="${@:2}"
+
+  if ! cargo run -q -- -S -o - $input > /dev/null 2> tmp.out; then
+    cat tmp.out
+    echo "$input => エラーになりました"
+    exit 1
+  fi
+  actual=$(grep -c ': warning: ' tmp.out)
+  if [ "$actual" = "$expected" ]; then
+    echo "$input => $actual warnings"
+  else
+    cat tmp.out
+    echo "$input => $expected warnings expected, but got $actual"
+    exit 1
+  fi
+  rm -f tmp.out
+}
+
+assert_warning 0 src/tests/warning/all.c
+assert_warning 1 -Wreturn-type src/tests/warning/all.c
+assert_warning 5 -Wall src/tests/warning/all.c
+assert_warning 3 -Wall -Wno-unused-variable src/tests/warning/all.c
+assert_warning 3 -Wno-unused-variable -Wall src/tests/warning/all.c
+assert_warning 5 -Wno-unused-variable -Wall -Wunused-variable src/tests/warning/all.c
+assert_warning 0 -Wreturn-type -Wno-return-type src/tests/warning/all.c
+assert_warning 1 -Wno-uninitialized -Wextra -Wuninitialized src/tests/warning/all.c
+assert_error 1 -Werror -Wreturn-type src/tests/warning/all.c
+
+# エラーと警告の出力がスナップショットとおなじかどうか
+assert_diagnostics() {
+  expected="$1"
+  input="${@:2}"
+
+  cargo run -q -- -S -o /dev/null $input 2> tmp.out
+  if diff -u "$expected" tmp.out; then
+    echo "$input => $expected"
+  else
+    echo "$input => $expected とちがいます"
+    exit 1
+  fi
+  rm -f tmp.out
+}
+
+assert_diagnostics src/tests/error/syntax.json --diagnostics-format=json src/tests/error/syntax.c
+assert_diagnostics src/tests/error/sema.sarif --diagnostics-format=sarif src/tests/error/sema.c
+assert_diagnostics src/tests/error/sema.txt src/tests/error/sema.c
+assert_diagnostics src/tests/error/syntax.color -fcolor-diagnostics src/tests/error/syntax.c
+assert_diagnostics src/tests/error/else.txt src/tests/error/else.c
+assert_diagnostics src/tests/error/endif.txt src/tests/error/endif.c
+
+# JSONの診断が標準エラー出力にひとつのドキュメントとして出て、expected個の診断が入っているかどうか
+# 翻訳単位ごとの警告も、あとで起きたエラーもまとめて出る
+assert_json() {
+  expected="$1"
+  input="${@:2}"
+
+  cargo run -q -- --diagnostics-format=json $input > /dev/null 2> tmp.out
+  if ! actual=$(python3 -c 'import json, sys; print(len(json.load(sys.stdin)["diagnostics"]))' < tmp.out); then
+    cat tmp.out
+    echo "$input => JSONとして読めません"
+    exit 1
+  fi
+  if [ "$actual" = "$expected" ]; then
+    echo "$input => $actual diagnostics"
+  else
+    cat tmp.out
+    echo "$input => $expected diagnostics expected, but got $actual"
+    exit 1
+  fi
+  rm -f tmp.out
+}
+
+# 2つの翻訳単位の警告と、3つめのファイルが読めないエラー
+assert_json 7 -S -Wall src/tests/warning/all.c src/tests/error/link.c src/tests/error/missing.c
+rm -f all.s link.s
+# 1つめの翻訳単位がエラーでも、2つめのエラーもまとめて出る
+assert_json 9 -S src/tests/error/syntax.c src/tests/error/sema.c
+# 警告とリンクのエラー
+assert_json 2 -Wall -o tmp src/tests/error/link.c
+# - で標準入力から読んで、-o - で標準出力に出す
+cargo run -q -- -S -o - - < src/tests/expr.c > tmp.s
+cc -o tmp tmp.s
+./tmp
+actual="$?"
+if [ "$actual" != 7 ]; then
+  echo "stdin => 7 expected, but got $actual"
+  exit 1
+fi
+echo "stdin => $actual"
+
+# -save-temps でも -o - なら実行ファイルは標準出力に出して、.s/.o はカレントディレクトリに残す
+cargo run -q -- -save-temps -o - src/tests/expr.c > tmp.out
+chmod +x tmp.out
+./tmp.out
+actual="$?"
+if [ "$actual" != 7 ] || [ ! -f expr.s ] || [ ! -f expr.o ]; then
+  echo "-save-temps -o - => 7 と expr.s/expr.o expected, but got $actual"
+  exit 1
+fi
+rm -f tmp.out expr.s expr.o
+echo "-save-temps -o - => $actual"
+
+# Cのファイルはどれもmainになるので、2つ以上はリンクする前にエラーにする
+if cargo run -q -- -o tmp src/tests/expr.c src/tests/comp.c 2> tmp.out; then
+  echo "2つのCのファイル => エラーになりませんでした"
+  exit 1
+fi
+if ! grep -q 'Cのファイルは1つしかリンクできません' tmp.out; then
+  cat tmp.out
+  echo "2つのCのファイル => エラーのメッセージがちがいます"
+  exit 1
+fi
+rm -f tmp.out
+echo "2つのCのファイル => エラー"
 
 echo OK
\ No newline at end of file